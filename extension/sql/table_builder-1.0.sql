@@ -4,23 +4,14 @@ This file is auto generated by pgx.
 The ordering of items is not stable, it is driven by a dependency graph.
 */
 
--- extension/src/lib.rs:20
--- table_builder::__table_builder_insert_InsertExample
-CREATE OR REPLACE FUNCTION public."__table_builder_insert_InsertExample"() RETURNS TABLE (
-	"foo" integer,  /* i32 */
-	"avg" real  /* core::option::Option<f32> */
-)
- STRICT
-LANGUAGE c /* Rust */
-AS 'MODULE_PATHNAME', '__table_builder_insert_InsertExample_wrapper';
-
 -- extension/src/lib.rs:20
 CREATE TABLE InsertExample (
     foo integer NOT NULL,
     avg real
 );
-INSERT INTO InsertExample SELECT * FROM "__table_builder_insert_InsertExample"();
-DROP FUNCTION "__table_builder_insert_InsertExample";
+CREATE FUNCTION pg_temp."__table_builder_insert_InsertExample"(text, text) RETURNS void LANGUAGE c AS 'MODULE_PATHNAME', '__table_builder_insert_InsertExample';
+SELECT pg_temp."__table_builder_insert_InsertExample"('InsertExample', '');
+DROP FUNCTION pg_temp."__table_builder_insert_InsertExample";
 
 
 -- extension/src/lib.rs:10
@@ -0,0 +1,50 @@
+// Checks the extension's tables against `sql/schema.lock.json`, or updates the
+// snapshot once the changes are ready to ship
+//
+//     cargo run --bin schema-lock -- check
+//     cargo run --bin schema-lock -- update
+use std::path::PathBuf;
+
+use table_builder::framework::snapshot::{self, Snapshot};
+
+fn main() {
+    let sql_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sql");
+    let lock_file = sql_dir.join("schema.lock.json");
+    let current = Snapshot::current(table_builder::extension_version(), table_builder::TABLES);
+
+    let command = std::env::args().nth(1);
+    match command.as_deref() {
+        Some("check") => {
+            let locked = match Snapshot::read(&lock_file) {
+                Ok(locked) => locked,
+                Err(error) => {
+                    eprintln!("could not read {}: {}", lock_file.display(), error);
+                    std::process::exit(1)
+                },
+            };
+            let upgrade_script = sql_dir.join(format!(
+                "table_builder--{}--{}.sql",
+                locked.version,
+                current.version,
+            ));
+            let result = snapshot::check(&locked, &current, table_builder::TABLES, &upgrade_script);
+            if let Err(errors) = result {
+                for error in errors {
+                    eprintln!("{}", error);
+                }
+                std::process::exit(1)
+            }
+        },
+        Some("update") => {
+            if let Err(error) = current.write(&lock_file) {
+                eprintln!("could not write {}: {}", lock_file.display(), error);
+                std::process::exit(1)
+            }
+            println!("wrote {}", lock_file.display());
+        },
+        _ => {
+            eprintln!("usage: schema-lock <check|update>");
+            std::process::exit(2)
+        },
+    }
+}
@@ -0,0 +1,34 @@
+// Writes the script to upgrade the extension between two versions, using the
+// history of the tables in `table_builder::TABLES`. Seed rows inserted with
+// `on conflict` are re-applied by every upgrade
+//
+//     cargo run --bin upgrade-generator -- 1.0 1.1
+//
+// writes `sql/table_builder--1.0--1.1.sql`.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (from, to) = match &*args {
+        [from, to] => (from, to),
+        _ => {
+            eprintln!("usage: upgrade-generator <from version> <to version>");
+            std::process::exit(2)
+        },
+    };
+
+    let script = table_builder::framework::upgrade::extension_upgrade_script(
+        from,
+        to,
+        table_builder::TABLES,
+    );
+    let path = format!(
+        "{}/sql/table_builder--{}--{}.sql",
+        env!("CARGO_MANIFEST_DIR"),
+        from,
+        to,
+    );
+    if let Err(error) = std::fs::write(&path, script) {
+        eprintln!("could not write {}: {}", path, error);
+        std::process::exit(1)
+    }
+    println!("wrote {}", path);
+}
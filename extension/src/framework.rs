@@ -1,13 +1,86 @@
+use pgx::pg_sys;
 
+pub mod catalog;
+pub mod client;
+pub mod copy;
+pub mod cursor;
+pub mod error;
+pub mod memory;
+pub mod schema;
+pub mod snapshot;
+pub mod transaction;
+pub mod upgrade;
 
-// marker trait that lets us know a struct was created with the `table!` macro
+// trait that lets us know a struct was created with the `table!` macro
 // and therefore it is safe to use in queries.
-pub unsafe trait PgTable {}
+pub unsafe trait PgTable {
+    // the table's SQL name, possibly schema-qualified
+    const NAME: &'static str;
+    // the columns as declared in rust, in order
+    const COLUMNS: &'static [Column];
+    // the columns of the primary key, if any
+    const KEY: &'static [&'static str] = &[];
+    // the SQL to upgrade the table between extension versions, in version order
+    const HISTORY: &'static [upgrade::Change] = &[];
+
+    // run before every query reading the table
+    fn before_query(_client: &pgx::SpiClient) {}
+
+    // the row's values in column order, for clients that keep rows outside
+    // postgres
+    fn into_values(self) -> Vec<client::Value> where Self: Sized;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub sql_type: &'static str,
+    pub nullable: bool,
+}
+
+// marker trait for the tables that can be written to. Tables that exist outside
+// of the extension, such as the builtin catalogs, are read-only; inserting
+// into one fails with "the trait `PgWritableTable` is not implemented"
+pub unsafe trait PgWritableTable: PgTable {
+    // the `CREATE TABLE` statement for the current version of the table
+    const CREATE: &'static str;
+    // statements that can be re-run to add or update the seed rows
+    const SEED: &'static str;
+
+    // the row's values in column order, as arguments to an `INSERT`
+    fn to_values_vec(self) -> Vec<(pgx::PgOid, Option<pgx::pg_sys::Datum>)> where Self: Sized;
+}
+
+// everything the extension knows about one of its tables, for the tools that
+// work on all of them at once
+#[derive(Debug, Clone, Copy)]
+pub struct TableDefinition {
+    pub name: &'static str,
+    pub columns: &'static [Column],
+    pub create: &'static str,
+    pub history: &'static [upgrade::Change],
+    pub seed: &'static str,
+}
+
+impl TableDefinition {
+    pub const fn of<T: PgWritableTable>() -> Self {
+        Self {
+            name: T::NAME,
+            columns: T::COLUMNS,
+            create: T::CREATE,
+            history: T::HISTORY,
+            seed: T::SEED,
+        }
+    }
+}
+
+pub fn assert_writable<T: PgWritableTable>() {}
 
 // trait that lets us know the equivalent SQL type for a rust type
 // this would likely be part of pgx in a real versin
 pub unsafe trait PgTyped {
     const SQL_TYPE: &'static str;
+    const NULLABLE: bool = false;
 }
 
 macro_rules! pg_typed {
@@ -28,10 +101,44 @@ pg_typed!(
     f64    => "double precision",
     String => "text",
     bool   => "boolean",
+    pg_sys::Oid => "oid",
+    Vec<i16> => "smallint[]",
+    Vec<pg_sys::Oid> => "oid[]",
+    Vec<String> => "text[]",
 );
 
 unsafe impl<T: PgTyped> PgTyped for Option<T> {
     const SQL_TYPE: &'static str = <T as PgTyped>::SQL_TYPE;
+    const NULLABLE: bool = true;
+}
+
+// the type postgres's `sum()` aggregate returns for a column of this type.
+// Sums of bigints are numerics, which are read as their text so that they
+// don't overflow
+pub unsafe trait PgSum {
+    type Sum: PgTyped;
+}
+
+macro_rules! pg_sum {
+    ($($t: ty => $sum: ty),* $(,)?) => {
+        $(
+            unsafe impl PgSum for $t {
+                type Sum = $sum;
+            }
+        )*
+    };
+}
+
+pg_sum!(
+    i16 => i64,
+    i32 => i64,
+    i64 => String,
+    f32 => f32,
+    f64 => f64,
+);
+
+unsafe impl<T: PgSum> PgSum for Option<T> {
+    type Sum = <T as PgSum>::Sum;
 }
 
 pub trait UnwrapTo<T> {
@@ -51,4 +158,122 @@ impl<T> UnwrapTo<Option<T>> for Option<T> {
     fn unwrap_to(self) -> Option<T> {
         self
     }
-}
\ No newline at end of file
+}
+
+// Postgres uses a 16-bit count for the parameters of a statement, so a single
+// multi-row INSERT can bind at most this many values.
+pub const MAX_STATEMENT_PARAMS: usize = u16::MAX as usize;
+
+// number of rows `query!(... values: ...)` batches into a single INSERT when no
+// `batch_size:` is given
+pub const DEFAULT_INSERT_BATCH_SIZE: usize = 1000;
+
+// builds `INSERT INTO table VALUES ($1, $2), ($3, $4), ...` for `rows` rows of
+// `columns` columns each
+pub fn insert_statement(table: &str, columns: usize, rows: usize) -> String {
+    use std::fmt::Write as _;
+
+    let mut sql = format!("INSERT INTO {} VALUES ", table);
+    let mut param = 1;
+    for row in 0..rows {
+        if row != 0 {
+            sql.push_str(", ");
+        }
+        sql.push('(');
+        for column in 0..columns {
+            if column != 0 {
+                sql.push_str(", ");
+            }
+            let _ = write!(&mut sql, "${}", param);
+            param += 1;
+        }
+        sql.push(')');
+    }
+    sql
+}
+
+// accumulates rows for a multi-row INSERT, sending them to the SPI whenever
+// `batch_size` rows (or as many as fit in one statement) have been collected
+pub struct InsertBatch<'t> {
+    table: &'t str,
+    // appended to each statement, e.g. an `ON CONFLICT` clause
+    suffix: &'t str,
+    batch_size: usize,
+    rows: usize,
+    args: Vec<(pgx::PgOid, Option<pgx::pg_sys::Datum>)>,
+    // rows the SPI reported as inserted so far
+    inserted: u64,
+}
+
+impl<'t> InsertBatch<'t> {
+    pub fn new(table: &'t str, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "INSERT batch size must be positive");
+        Self {
+            table,
+            suffix: "",
+            batch_size,
+            rows: 0,
+            args: vec![],
+            inserted: 0,
+        }
+    }
+
+    pub fn suffix(self, suffix: &'t str) -> Self {
+        Self { suffix, ..self }
+    }
+
+    pub fn push(
+        &mut self,
+        client: &mut pgx::SpiClient,
+        row: Vec<(pgx::PgOid, Option<pgx::pg_sys::Datum>)>,
+    ) {
+        let columns = row.len();
+        if self.rows > 0 && self.args.len() + columns > MAX_STATEMENT_PARAMS {
+            self.flush(client);
+        }
+        self.args.extend(row);
+        self.rows += 1;
+        if self.rows >= self.batch_size {
+            self.flush(client);
+        }
+    }
+
+    // returns the number of rows inserted, which may be fewer than were pushed
+    // if the suffix skips conflicting rows
+    pub fn finish(mut self, client: &mut pgx::SpiClient) -> u64 {
+        self.flush(client);
+        self.inserted
+    }
+
+    fn flush(&mut self, client: &mut pgx::SpiClient) {
+        if self.rows == 0 {
+            return
+        }
+        let columns = self.args.len() / self.rows;
+        let mut query = insert_statement(self.table, columns, self.rows);
+        query.push_str(self.suffix);
+        let args = std::mem::take(&mut self.args);
+        self.rows = 0;
+        // TODO every full batch has the same statement, we should cache its
+        // plan, but the APIs aren't exposed
+        let processed = client.update(&query, None, Some(args)).len();
+        self.inserted += processed as u64;
+    }
+}
+
+// used by the functions `table!` generates for `insert` blocks, which the
+// extension script calls to seed the table
+pub fn seed_rows(
+    table: &str,
+    suffix: &str,
+    rows: impl Iterator<Item = Vec<(pgx::PgOid, Option<pgx::pg_sys::Datum>)>>,
+) {
+    pgx::Spi::connect(|mut client| {
+        let mut batch = InsertBatch::new(table, DEFAULT_INSERT_BATCH_SIZE).suffix(suffix);
+        for row in rows {
+            batch.push(&mut client, row);
+        }
+        batch.finish(&mut client);
+        Ok(Some(()))
+    });
+}
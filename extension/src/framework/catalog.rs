@@ -0,0 +1,171 @@
+// Read-only definitions of the builtin catalog tables most often used by
+// extensions. The struct names match the catalog names so that they can be used
+// in `query!` as-is, e.g.
+//
+//     query!(client
+//         from: pg_class join pg_namespace on pg_class.relnamespace = pg_namespace.oid
+//         select: (pg_class.relname, pg_namespace.nspname)
+//     )
+//
+// Only columns that are present, and have the same type, in every supported
+// version of postgres are declared. `name` and `"char"` columns are read as
+// `text`, and `int2vector`/`oidvector` columns as arrays.
+#![allow(non_camel_case_types)]
+
+use pgx::pg_sys::Oid;
+
+use table_builder_macro::extern_table;
+
+use crate::framework;
+
+extern_table!{
+    pub pg_namespace (
+        oid: Oid,
+        nspname: String,
+        nspowner: Oid,
+    )
+    name: "pg_catalog.pg_namespace"
+}
+
+extern_table!{
+    pub pg_class (
+        oid: Oid,
+        relname: String,
+        relnamespace: Oid,
+        reltype: Oid,
+        relowner: Oid,
+        relam: Oid,
+        relfilenode: Oid,
+        reltablespace: Oid,
+        relpages: i32,
+        reltuples: f32,
+        relallvisible: i32,
+        reltoastrelid: Oid,
+        relhasindex: bool,
+        relisshared: bool,
+        relpersistence: String,
+        relkind: String,
+        relnatts: i16,
+        relchecks: i16,
+        relhasrules: bool,
+        relhastriggers: bool,
+        relhassubclass: bool,
+        relrowsecurity: bool,
+        relforcerowsecurity: bool,
+        relispopulated: bool,
+        relreplident: String,
+        relispartition: bool,
+    )
+    name: "pg_catalog.pg_class"
+}
+
+extern_table!{
+    pub pg_attribute (
+        attrelid: Oid,
+        attname: String,
+        atttypid: Oid,
+        attlen: i16,
+        attnum: i16,
+        attndims: i32,
+        atttypmod: i32,
+        attnotnull: bool,
+        atthasdef: bool,
+        attidentity: String,
+        attisdropped: bool,
+        attislocal: bool,
+        attinhcount: i32,
+        attcollation: Oid,
+    )
+    name: "pg_catalog.pg_attribute"
+}
+
+extern_table!{
+    pub pg_proc (
+        oid: Oid,
+        proname: String,
+        pronamespace: Oid,
+        proowner: Oid,
+        prolang: Oid,
+        procost: f32,
+        prorows: f32,
+        provariadic: Oid,
+        proisstrict: bool,
+        proretset: bool,
+        provolatile: String,
+        pronargs: i16,
+        pronargdefaults: i16,
+        prorettype: Oid,
+        proargtypes: Vec<Oid>,
+        prosrc: String,
+        probin: Option<String>,
+    )
+    name: "pg_catalog.pg_proc"
+}
+
+extern_table!{
+    pub pg_type (
+        oid: Oid,
+        typname: String,
+        typnamespace: Oid,
+        typowner: Oid,
+        typlen: i16,
+        typbyval: bool,
+        typtype: String,
+        typcategory: String,
+        typisdefined: bool,
+        typrelid: Oid,
+        typelem: Oid,
+        typarray: Oid,
+        typnotnull: bool,
+        typbasetype: Oid,
+        typtypmod: i32,
+        typndims: i32,
+        typcollation: Oid,
+    )
+    name: "pg_catalog.pg_type"
+}
+
+extern_table!{
+    pub pg_index (
+        indexrelid: Oid,
+        indrelid: Oid,
+        indnatts: i16,
+        indisunique: bool,
+        indisprimary: bool,
+        indisexclusion: bool,
+        indimmediate: bool,
+        indisclustered: bool,
+        indisvalid: bool,
+        indisready: bool,
+        indislive: bool,
+        indkey: Vec<i16>,
+    )
+    name: "pg_catalog.pg_index"
+}
+
+extern_table!{
+    pub pg_depend (
+        classid: Oid,
+        objid: Oid,
+        objsubid: i32,
+        refclassid: Oid,
+        refobjid: Oid,
+        refobjsubid: i32,
+        deptype: String,
+    )
+    name: "pg_catalog.pg_depend"
+}
+
+extern_table!{
+    pub pg_extension (
+        oid: Oid,
+        extname: String,
+        extowner: Oid,
+        extnamespace: Oid,
+        extrelocatable: bool,
+        extversion: String,
+        extconfig: Option<Vec<Oid>>,
+        extcondition: Option<Vec<String>>,
+    )
+    name: "pg_catalog.pg_extension"
+}
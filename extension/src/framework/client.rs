@@ -0,0 +1,297 @@
+// The interface `query!` runs queries through.
+//
+// `query!` describes each query both as SQL and as a `Select`, and hands it to
+// whatever implements `Client`: pgx's `SpiClient` runs the SQL, while
+// `MemoryClient` evaluates the description against rows it keeps in memory, so
+// code built on our tables can be unit tested without a database.
+
+use pgx::{pg_sys, FromDatum, SpiClient, SpiHeapTupleData};
+
+use super::{
+    cursor, error::{PgError, QueryError}, transaction, Column, InsertBatch, PgTable, PgTyped, PgWritableTable,
+};
+
+pub type Rows<'c, R> = Box<dyn Iterator<Item = R> + 'c>;
+
+pub trait Client {
+    type Row: Row;
+
+    // run before every query reading `T`
+    fn before_query<T: PgTable>(&self);
+
+    fn select_rows<'c>(&'c self, query: &Select) -> Result<Rows<'c, Self::Row>, QueryError>;
+
+    // row-locking selects, which need a read-write connection
+    fn select_rows_mut<'c>(&'c mut self, query: &Select) -> Result<Rows<'c, Self::Row>, QueryError> {
+        self.select_rows(query)
+    }
+
+    // insert `rows`, at most `batch_size` per statement, returning the number
+    // inserted. Either all of them are inserted or none are
+    fn insert_rows<T: PgWritableTable>(
+        &mut self,
+        rows: impl IntoIterator<Item = T>,
+        batch_size: usize,
+    ) -> Result<u64, QueryError>;
+
+    // run `f` in a savepoint, which is rolled back if `f` returns an error or
+    // postgres raises one. In the latter case the postgres error is returned
+    fn savepoint<R, E: From<PgError>>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<R, E>,
+    ) -> Result<R, E>;
+}
+
+// the only row of a `select_one:`
+pub fn one_row<R>(mut rows: impl Iterator<Item = R>) -> Result<R, QueryError> {
+    let row = rows.next().ok_or_else(QueryError::not_found)?;
+    if rows.next().is_some() {
+        return Err(QueryError::too_many())
+    }
+    Ok(row)
+}
+
+// the row of a `select_optional:`, if there is one
+pub fn optional_row<R>(mut rows: impl Iterator<Item = R>) -> Result<Option<R>, QueryError> {
+    let row = rows.next();
+    if row.is_some() && rows.next().is_some() {
+        return Err(QueryError::too_many())
+    }
+    Ok(row)
+}
+
+// a row of query results, columns are numbered from 1 like in SPI
+pub trait Row {
+    // `None` if the column is NULL
+    fn get<T: PgValue>(&self, ordinal: usize) -> Option<T>;
+}
+
+// a query, as generated by `query!`
+#[derive(Debug, Clone, Copy)]
+pub struct Select<'q> {
+    // the whole query, for clients that speak SQL
+    pub sql: &'q str,
+    // the table in `from` followed by the joined ones, in order
+    pub tables: &'q [TableRef],
+    pub joins: &'q [Join],
+    pub columns: &'q [Selected],
+    // the `where:` string, as SQL
+    pub filter: Option<&'q str>,
+    pub distinct: Distinct<'q>,
+    pub group_by: &'q [ColumnRef],
+    pub order_by: &'q [OrderBy],
+    pub limit: Option<i64>,
+    pub fetch_size: Option<i64>,
+    // false for row-locking selects
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TableRef {
+    // the rust name, which columns are qualified by in the query
+    pub alias: &'static str,
+    pub name: &'static str,
+    pub columns: &'static [Column],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnRef {
+    // index into `Select::tables`
+    pub table: usize,
+    pub column: &'static str,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Join {
+    // index into `Select::tables`
+    pub table: usize,
+    pub left: bool,
+    pub on: (ColumnRef, ColumnRef),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Distinct<'q> {
+    // every row, SQL's default `ALL`
+    All,
+    // `DISTINCT`, rows differing in any selected column
+    Rows,
+    // `DISTINCT ON`, the first row for each value of the columns
+    On(&'q [ColumnRef]),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrderBy {
+    pub column: ColumnRef,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Selected {
+    Column(ColumnRef),
+    // `None` for `count(*)`
+    Count(Option<ColumnRef>),
+    Min(ColumnRef),
+    Max(ColumnRef),
+    Sum(ColumnRef),
+}
+
+// a column value held outside of postgres
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int2(i16),
+    Int4(i32),
+    Int8(i64),
+    Float4(f32),
+    Float8(f64),
+    Text(String),
+    Oid(pg_sys::Oid),
+    Array(Vec<Value>),
+}
+
+// the types a column can be read as
+pub trait PgValue: PgTyped + FromDatum + Into<Value> {
+    // panics if `value` is of another type, or NULL
+    fn from_value(value: Value) -> Self;
+}
+
+macro_rules! pg_value {
+    ($($t: ty => $variant: ident),* $(,)?) => {
+        $(
+            impl From<$t> for Value {
+                fn from(value: $t) -> Self {
+                    Value::$variant(value)
+                }
+            }
+
+            impl PgValue for $t {
+                fn from_value(value: Value) -> Self {
+                    match value {
+                        Value::$variant(value) => value,
+                        value => panic!(
+                            "expected a {} value, found {:?}",
+                            <$t as PgTyped>::SQL_TYPE, value,
+                        ),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+pg_value!(
+    i16    => Int2,
+    i32    => Int4,
+    i64    => Int8,
+    f32    => Float4,
+    f64    => Float8,
+    String => Text,
+    bool   => Bool,
+    pg_sys::Oid => Oid,
+);
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: PgValue> PgValue for Vec<T> where Vec<T>: PgTyped + FromDatum {
+    fn from_value(value: Value) -> Self {
+        match value {
+            Value::Array(values) => values.into_iter().map(T::from_value).collect(),
+            value => panic!("expected an array, found {:?}", value),
+        }
+    }
+}
+
+impl Client for SpiClient {
+    type Row = SpiHeapTupleData;
+
+    fn before_query<T: PgTable>(&self) {
+        T::before_query(self)
+    }
+
+    // streaming selects only catch errors raised while opening the cursor,
+    // which covers the casts of mismatched columns but not errors in later
+    // batches
+    fn select_rows<'c>(&'c self, query: &Select) -> Result<Rows<'c, Self::Row>, QueryError> {
+        statement(move || {
+            let rows: Rows<Self::Row> = match query.fetch_size {
+                Some(fetch_size) => Box::new(
+                    cursor::open_cursor(self, query.sql, fetch_size, query.read_only)
+                ),
+                None => Box::new(self.select(query.sql, None, None)),
+            };
+            Ok(rows)
+        })
+    }
+
+    fn select_rows_mut<'c>(&'c mut self, query: &Select) -> Result<Rows<'c, Self::Row>, QueryError> {
+        match query.fetch_size {
+            Some(_) => self.select_rows(query),
+            None => statement(move || {
+                let rows: Rows<Self::Row> = Box::new(self.update(query.sql, None, None));
+                Ok(rows)
+            }),
+        }
+    }
+
+    fn insert_rows<T: PgWritableTable>(
+        &mut self,
+        rows: impl IntoIterator<Item = T>,
+        batch_size: usize,
+    ) -> Result<u64, QueryError> {
+        statement(move || {
+            let mut batch = InsertBatch::new(T::NAME, batch_size);
+            for row in rows {
+                batch.push(self, row.to_values_vec());
+            }
+            Ok(batch.finish(self))
+        })
+    }
+
+    fn savepoint<R, E: From<PgError>>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<R, E>,
+    ) -> Result<R, E> {
+        transaction::savepoint(|| f(self))
+    }
+}
+
+// each statement runs in a savepoint of its own, so that an error postgres
+// raises only undoes the statement and is returned as a `QueryError`. A
+// subtransaction that only reads never takes a transaction id, but each one that
+// writes fills a slot of the backend's cache of 64 subtransaction ids until the
+// transaction ends, past which snapshots slow down across the cluster; a
+// multi-row insert takes a single one however many batches it sends.
+// Subtransactions can't be started in parallel mode, where errors abort the
+// transaction as usual
+fn statement<R>(f: impl FnOnce() -> Result<R, QueryError>) -> Result<R, QueryError> {
+    if unsafe { pg_sys::IsInParallelMode() } {
+        return f()
+    }
+    transaction::savepoint(f)
+}
+
+impl Row for SpiHeapTupleData {
+    fn get<T: PgValue>(&self, ordinal: usize) -> Option<T> {
+        self.by_ordinal(ordinal).unwrap().value()
+    }
+}
+
+impl Row for Vec<Value> {
+    fn get<T: PgValue>(&self, ordinal: usize) -> Option<T> {
+        match &self[ordinal - 1] {
+            Value::Null => None,
+            value => Some(T::from_value(value.clone())),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+// COPY-based bulk loading for tables created by `table!`.
+//
+// Rows are turned into datums by the same `to_values_vec()` used for INSERTs,
+// encoded with each column type's binary send function, and streamed into the
+// table through `COPY ... FROM` in binary format. Rows are pulled from the
+// iterator only as COPY asks for more input, so the whole data set is never
+// materialized.
+
+use std::{
+    cell::Cell,
+    ffi::CString,
+    os::raw::{c_char, c_int, c_void},
+    ptr,
+};
+
+use pgx::*;
+
+type Row = Vec<(PgOid, Option<pg_sys::Datum>)>;
+
+const SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+
+// COPY's data-source callback has no user-data argument, so the active source
+// is stashed here for the duration of `copy_in()`
+thread_local! {
+    static SOURCE: Cell<*mut CopySource<'static>> = Cell::new(ptr::null_mut());
+}
+
+// COPY `rows` into `table`, returning the number of rows written
+pub fn copy_in(table: &str, rows: impl Iterator<Item = Row>) -> u64 {
+    let mut rows = rows;
+    let mut source = CopySource::new(&mut rows);
+    let _active = ActiveSource::set(&mut source);
+    unsafe { copy_from_source(table) }
+}
+
+struct CopySource<'r> {
+    rows: &'r mut dyn Iterator<Item = Row>,
+    send_fns: Vec<pg_sys::Oid>,
+    buffer: Vec<u8>,
+    // number of bytes at the start of `buffer` that COPY has already consumed
+    consumed: usize,
+    done: bool,
+    memcxt: PgMemoryContexts,
+}
+
+impl<'r> CopySource<'r> {
+    fn new(rows: &'r mut dyn Iterator<Item = Row>) -> Self {
+        let mut buffer = SIGNATURE.to_vec();
+        // flags, then header-extension length
+        buffer.extend(0i32.to_be_bytes());
+        buffer.extend(0i32.to_be_bytes());
+        Self {
+            rows,
+            send_fns: vec![],
+            buffer,
+            consumed: 0,
+            done: false,
+            memcxt: PgMemoryContexts::new("table_builder COPY rows"),
+        }
+    }
+
+    // copy at least `min` bytes into `out`, unless the input is exhausted
+    fn read_into(&mut self, out: &mut [u8], min: usize) -> usize {
+        while self.buffer.len() - self.consumed < min && !self.done {
+            self.buffer.drain(..self.consumed);
+            self.consumed = 0;
+            self.encode_next_row();
+        }
+        let available = &self.buffer[self.consumed..];
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consumed += len;
+        len
+    }
+
+    fn encode_next_row(&mut self) {
+        let CopySource { rows, send_fns, buffer, memcxt, .. } = self;
+        // the row's datums and their binary encodings only live until the
+        // bytes are in `buffer`
+        let more = memcxt.switch_to(|_| match rows.next() {
+            Some(row) => {
+                encode_row(send_fns, buffer, row);
+                true
+            },
+            None => false,
+        });
+        memcxt.reset();
+        if !more {
+            buffer.extend((-1i16).to_be_bytes());
+            self.done = true;
+        }
+    }
+}
+
+fn encode_row(send_fns: &mut Vec<pg_sys::Oid>, buffer: &mut Vec<u8>, row: Row) {
+    if send_fns.is_empty() {
+        send_fns.extend(row.iter().map(|(oid, _)| unsafe {
+            let mut send_fn = pg_sys::InvalidOid;
+            let mut is_varlena = false;
+            pg_sys::getTypeBinaryOutputInfo(oid.value(), &mut send_fn, &mut is_varlena);
+            send_fn
+        }));
+    }
+
+    buffer.extend((row.len() as i16).to_be_bytes());
+    for ((_, datum), &send_fn) in row.into_iter().zip(send_fns.iter()) {
+        match datum {
+            None => buffer.extend((-1i32).to_be_bytes()),
+            Some(datum) => unsafe {
+                let bytes = pg_sys::OidSendFunctionCall(send_fn, datum);
+                let bytes = varlena_to_byte_slice(bytes as *const pg_sys::varlena);
+                buffer.extend((bytes.len() as i32).to_be_bytes());
+                buffer.extend_from_slice(bytes);
+            },
+        }
+    }
+}
+
+struct ActiveSource;
+
+impl ActiveSource {
+    fn set(source: &mut CopySource<'_>) -> Self {
+        SOURCE.with(|active| {
+            assert!(active.get().is_null(), "nested copy_in() is not supported");
+            active.set(source as *mut CopySource<'_> as *mut CopySource<'static>)
+        });
+        Self
+    }
+}
+
+impl Drop for ActiveSource {
+    fn drop(&mut self) {
+        SOURCE.with(|active| active.set(ptr::null_mut()))
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn read_source(outbuf: *mut c_void, minread: c_int, maxread: c_int) -> c_int {
+    let source = SOURCE.with(|active| active.get());
+    assert!(!source.is_null(), "COPY data requested outside of copy_in()");
+    let out = std::slice::from_raw_parts_mut(outbuf as *mut u8, maxread as usize);
+    (*source).read_into(out, minread as usize) as c_int
+}
+
+unsafe fn copy_from_source(table: &str) -> u64 {
+    let name = CString::new(table).expect("table name contains a NUL byte");
+    let range_var = pg_sys::makeRangeVarFromNameList(
+        pg_sys::stringToQualifiedNameList(name.as_ptr())
+    );
+    let lockmode = pg_sys::RowExclusiveLock as pg_sys::LOCKMODE;
+    let rel = pg_sys::relation_openrv(range_var, lockmode);
+
+    let relid = (*rel).rd_id;
+    let acl = pg_sys::pg_class_aclcheck(relid, pg_sys::GetUserId(), pg_sys::ACL_INSERT as _);
+    if acl != pg_sys::AclResult_ACLCHECK_OK {
+        error!("permission denied for table {}", table)
+    }
+    // COPY FROM doesn't apply row-level security policies, so like COPY itself
+    // refuse tables they'd be enforced on
+    let rls = pg_sys::check_enable_rls(relid, pg_sys::InvalidOid, false);
+    if rls == pg_sys::CheckEnableRlsResult_RLS_ENABLED as _ {
+        ereport!(
+            PgLogLevel::ERROR,
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "COPY FROM not supported with row-level security"
+        );
+    }
+
+    let pstate = pg_sys::make_parsestate(ptr::null_mut());
+    add_range_table_entry(pstate, rel, lockmode);
+
+    let format = pg_sys::makeDefElem(
+        pg_cstr("format"),
+        pg_sys::makeString(pg_cstr("binary")) as *mut pg_sys::Node,
+        -1,
+    );
+    let options = pg_sys::lappend(ptr::null_mut(), format as *mut c_void);
+
+    let cstate = begin_copy_from(pstate, rel, options);
+    let processed = pg_sys::CopyFrom(cstate);
+    pg_sys::EndCopyFrom(cstate);
+
+    // like COPY itself, hold the lock until the end of the transaction
+    pg_sys::relation_close(rel, pg_sys::NoLock as _);
+    processed
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn add_range_table_entry(
+    _pstate: *mut pg_sys::ParseState,
+    _rel: pg_sys::Relation,
+    _lockmode: pg_sys::LOCKMODE,
+) {
+    // before pg12 COPY's executor state doesn't need a range table
+}
+
+#[cfg(feature = "pg12")]
+unsafe fn add_range_table_entry(
+    pstate: *mut pg_sys::ParseState,
+    rel: pg_sys::Relation,
+    lockmode: pg_sys::LOCKMODE,
+) {
+    let rte = pg_sys::addRangeTableEntryForRelation(
+        pstate, rel, lockmode, ptr::null_mut(), false, false,
+    );
+    (*rte).requiredPerms = pg_sys::ACL_INSERT as _;
+}
+
+#[cfg(any(feature = "pg13", feature = "pg14"))]
+unsafe fn add_range_table_entry(
+    pstate: *mut pg_sys::ParseState,
+    rel: pg_sys::Relation,
+    lockmode: pg_sys::LOCKMODE,
+) {
+    let item = pg_sys::addRangeTableEntryForRelation(
+        pstate, rel, lockmode, ptr::null_mut(), false, false,
+    );
+    (*(*item).p_rte).requiredPerms = pg_sys::ACL_INSERT as _;
+}
+
+#[cfg(not(feature = "pg14"))]
+unsafe fn begin_copy_from(
+    pstate: *mut pg_sys::ParseState,
+    rel: pg_sys::Relation,
+    options: *mut pg_sys::List,
+) -> pg_sys::CopyState {
+    pg_sys::BeginCopyFrom(
+        pstate,
+        rel,
+        ptr::null(),
+        false,
+        Some(read_source),
+        ptr::null_mut(),
+        options,
+    )
+}
+
+#[cfg(feature = "pg14")]
+unsafe fn begin_copy_from(
+    pstate: *mut pg_sys::ParseState,
+    rel: pg_sys::Relation,
+    options: *mut pg_sys::List,
+) -> pg_sys::CopyFromState {
+    pg_sys::BeginCopyFrom(
+        pstate,
+        rel,
+        ptr::null_mut(),
+        ptr::null(),
+        false,
+        Some(read_source),
+        ptr::null_mut(),
+        options,
+    )
+}
+
+fn pg_cstr(s: &str) -> *mut c_char {
+    let s = CString::new(s).unwrap();
+    unsafe { pg_sys::pstrdup(s.as_ptr()) }
+}
@@ -0,0 +1,110 @@
+// Streaming SELECTs backed by an SPI cursor.
+//
+// `SpiClient::select()` materializes the entire result set before returning,
+// which is fine for small catalog tables, but not for large ones. `SpiCursor`
+// instead fetches `fetch_size` rows at a time, freeing each batch before
+// fetching the next, and yields the same `SpiHeapTupleData` that
+// `SpiClient::select()` does so `query!` can decode rows identically.
+
+use std::{ffi::CString, marker::PhantomData, os::raw::c_long, ptr};
+
+use pgx::*;
+
+pub struct SpiCursor<'c> {
+    portal: pg_sys::Portal,
+    fetch_size: c_long,
+    table: *mut pg_sys::SPITupleTable,
+    len: usize,
+    next: usize,
+    exhausted: bool,
+    // the cursor only lives as long as the SPI connection
+    _client: PhantomData<&'c SpiClient>,
+}
+
+pub fn open_cursor<'c>(
+    _client: &'c SpiClient,
+    query: &str,
+    fetch_size: i64,
+    read_only: bool,
+) -> SpiCursor<'c> {
+    assert!(fetch_size > 0, "cursor fetch size must be positive");
+    let query = CString::new(query).expect("query contains a NUL byte");
+    let portal = unsafe {
+        pg_sys::SPI_cursor_open_with_args(
+            ptr::null(),
+            query.as_ptr(),
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null(),
+            read_only,
+            0,
+        )
+    };
+    if portal.is_null() {
+        error!("could not open cursor for \"{}\"", query.to_string_lossy())
+    }
+    SpiCursor {
+        portal,
+        fetch_size: fetch_size as c_long,
+        table: ptr::null_mut(),
+        len: 0,
+        next: 0,
+        exhausted: false,
+        _client: PhantomData,
+    }
+}
+
+impl<'c> SpiCursor<'c> {
+    fn fetch(&mut self) {
+        unsafe {
+            self.free_batch();
+            pg_sys::SPI_cursor_fetch(self.portal, true, self.fetch_size);
+            self.table = pg_sys::SPI_tuptable;
+            self.len = if self.table.is_null() { 0 } else { pg_sys::SPI_processed as usize };
+        }
+        self.next = 0;
+        if self.len < self.fetch_size as usize {
+            self.exhausted = true;
+        }
+    }
+
+    unsafe fn free_batch(&mut self) {
+        if !self.table.is_null() {
+            pg_sys::SPI_freetuptable(self.table);
+            self.table = ptr::null_mut();
+            self.len = 0;
+        }
+    }
+}
+
+impl<'c> Iterator for SpiCursor<'c> {
+    type Item = SpiHeapTupleData;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            if self.exhausted {
+                return None
+            }
+            self.fetch();
+            if self.len == 0 {
+                return None
+            }
+        }
+        let tuple = unsafe {
+            let table = &*self.table;
+            SpiHeapTupleData::new(table.tupdesc, *table.vals.add(self.next))
+        };
+        self.next += 1;
+        Some(tuple)
+    }
+}
+
+impl<'c> Drop for SpiCursor<'c> {
+    fn drop(&mut self) {
+        unsafe {
+            self.free_batch();
+            pg_sys::SPI_cursor_close(self.portal);
+        }
+    }
+}
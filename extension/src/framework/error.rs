@@ -0,0 +1,184 @@
+// Errors raised by postgres, as rust values.
+
+use std::{ffi::CStr, fmt, os::raw::c_char};
+
+use pgx::pg_sys;
+
+// SQLSTATE codes callers commonly need to tell apart, see
+// https://www.postgresql.org/docs/current/errcodes-appendix.html
+pub mod sqlstate {
+    pub const FEATURE_NOT_SUPPORTED: &str = "0A000";
+    pub const NOT_NULL_VIOLATION: &str = "23502";
+    pub const FOREIGN_KEY_VIOLATION: &str = "23503";
+    pub const UNIQUE_VIOLATION: &str = "23505";
+    pub const CHECK_VIOLATION: &str = "23514";
+    pub const AMBIGUOUS_COLUMN: &str = "42702";
+    pub const UNDEFINED_COLUMN: &str = "42703";
+    pub const DATATYPE_MISMATCH: &str = "42804";
+    pub const CANNOT_COERCE: &str = "42846";
+    pub const UNDEFINED_TABLE: &str = "42P01";
+    pub const NO_DATA_FOUND: &str = "P0002";
+    pub const TOO_MANY_ROWS: &str = "P0003";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgError {
+    // the five character SQLSTATE, e.g. "23505"
+    pub code: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl PgError {
+    // takes the error postgres is currently handling, clearing its error state.
+    // must only be called after postgres has raised an error, and outside of
+    // `ErrorContext`
+    pub(crate) unsafe fn take() -> Self {
+        let data = pg_sys::CopyErrorData();
+        pg_sys::FlushErrorState();
+        let error = Self {
+            code: unpack_sql_state((*data).sqlerrcode),
+            message: string((*data).message).unwrap_or_default(),
+            detail: string((*data).detail),
+            hint: string((*data).hint),
+        };
+        pg_sys::FreeErrorData(data);
+        error
+    }
+
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.into(),
+            detail: None,
+            hint: None,
+        }
+    }
+}
+
+impl fmt::Display for PgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (SQLSTATE {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for PgError {}
+
+// postgres packs the five characters of a SQLSTATE into six bits each
+fn unpack_sql_state(code: i32) -> String {
+    (0..5)
+        .map(|i| (((code >> (6 * i)) & 0x3F) as u8 + b'0') as char)
+        .collect()
+}
+
+unsafe fn string(s: *const c_char) -> Option<String> {
+    if s.is_null() {
+        return None
+    }
+    Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+}
+
+// an error raised by postgres while running a `query!`, classified by its
+// SQLSTATE. The constraint violations are problems with the data, while the
+// rest usually mean the table no longer matches its declaration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    UniqueViolation(PgError),
+    ForeignKeyViolation(PgError),
+    NotNullViolation(PgError),
+    CheckViolation(PgError),
+    // a column can't be cast to the type it was declared with
+    CannotCoerce(PgError),
+    UndefinedTable(PgError),
+    UndefinedColumn(PgError),
+    // a `select_one:` found no rows
+    NotFound(PgError),
+    // a `select_one:` or `select_optional:` found more than one row
+    TooMany(PgError),
+    Other(PgError),
+}
+
+impl QueryError {
+    // the same errors PL/pgSQL's `SELECT INTO STRICT` raises
+    pub fn not_found() -> Self {
+        Self::NotFound(PgError::new(sqlstate::NO_DATA_FOUND, "query returned no rows"))
+    }
+
+    pub fn too_many() -> Self {
+        Self::TooMany(PgError::new(sqlstate::TOO_MANY_ROWS, "query returned more than one row"))
+    }
+
+    pub fn error(&self) -> &PgError {
+        use QueryError::*;
+
+        match self {
+            UniqueViolation(error)
+            | ForeignKeyViolation(error)
+            | NotNullViolation(error)
+            | CheckViolation(error)
+            | CannotCoerce(error)
+            | UndefinedTable(error)
+            | UndefinedColumn(error)
+            | NotFound(error)
+            | TooMany(error)
+            | Other(error) => error,
+        }
+    }
+
+    // the SQLSTATE
+    pub fn code(&self) -> &str {
+        &self.error().code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.error().message
+    }
+}
+
+impl From<PgError> for QueryError {
+    fn from(error: PgError) -> Self {
+        use QueryError::*;
+
+        let variant = match &*error.code {
+            sqlstate::UNIQUE_VIOLATION => UniqueViolation,
+            sqlstate::FOREIGN_KEY_VIOLATION => ForeignKeyViolation,
+            sqlstate::NOT_NULL_VIOLATION => NotNullViolation,
+            sqlstate::CHECK_VIOLATION => CheckViolation,
+            sqlstate::CANNOT_COERCE => CannotCoerce,
+            sqlstate::UNDEFINED_TABLE => UndefinedTable,
+            sqlstate::UNDEFINED_COLUMN => UndefinedColumn,
+            sqlstate::NO_DATA_FOUND => NotFound,
+            sqlstate::TOO_MANY_ROWS => TooMany,
+            _ => Other,
+        };
+        variant(error)
+    }
+}
+
+impl From<QueryError> for PgError {
+    fn from(error: QueryError) -> Self {
+        use QueryError::*;
+
+        match error {
+            UniqueViolation(error)
+            | ForeignKeyViolation(error)
+            | NotNullViolation(error)
+            | CheckViolation(error)
+            | CannotCoerce(error)
+            | UndefinedTable(error)
+            | UndefinedColumn(error)
+            | NotFound(error)
+            | TooMany(error)
+            | Other(error) => error,
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error().fmt(f)
+    }
+}
+
+impl std::error::Error for QueryError {}
@@ -0,0 +1,357 @@
+// A `Client` that keeps its tables in memory, for unit testing code that uses
+// `query!` without a database.
+//
+// Rows are stored per table, in insertion order, and selects are evaluated
+// directly against them: joins, `where`, `distinct`, `group by` with its
+// aggregates, `order by` and `limit` all work. `where` strings are interpreted
+// by a small subset of SQL, see `filter`; anything outside it is an error.
+// Locking clauses, `before_query` checks and `fetch_size` have nothing to do in
+// memory and are ignored.
+// Inserting a duplicate primary key fails with a `QueryError::UniqueViolation`,
+// as it would in postgres.
+
+use std::{cmp::Ordering, collections::HashMap, panic::AssertUnwindSafe};
+
+use super::{
+    client::{Client, ColumnRef, Distinct, Rows, Select, Selected, Value},
+    error::{sqlstate, PgError, QueryError},
+    PgTable, PgWritableTable,
+};
+
+mod filter;
+
+// a row of each table in the query, `None` for the missing side of a left join
+type Joined<'r> = Vec<Option<&'r [Value]>>;
+
+#[derive(Debug, Default, Clone)]
+pub struct MemoryClient {
+    // keyed by `PgTable::NAME`
+    tables: HashMap<&'static str, Vec<Vec<Value>>>,
+}
+
+impl MemoryClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // add rows to any table, including read-only ones such as the catalogs,
+    // which `query!` can't insert into
+    pub fn with_rows<T: PgTable>(mut self, rows: impl IntoIterator<Item = T>) -> Self {
+        if let Err(error) = self.push_rows(rows) {
+            panic!("{}", error)
+        }
+        self
+    }
+
+    // the rows of `T`, in insertion order
+    pub fn rows<T: PgTable>(&self) -> &[Vec<Value>] {
+        self.tables.get(T::NAME).map_or(&[], |rows| &**rows)
+    }
+
+    // either all of `rows` are added, or none are
+    fn push_rows<T: PgTable>(&mut self, rows: impl IntoIterator<Item = T>) -> Result<u64, QueryError> {
+        let key: Vec<_> = T::KEY.iter()
+            .map(|key| column_index(T::COLUMNS, key))
+            .collect();
+        let table = self.tables.entry(T::NAME).or_default();
+        let len = table.len();
+        for row in rows {
+            let row = row.into_values();
+            let duplicate = !key.is_empty() && table.iter()
+                .any(|existing| key.iter().all(|&i| existing[i] == row[i]));
+            if duplicate {
+                table.truncate(len);
+                let message = format!("duplicate key value violates the primary key of \"{}\"", T::NAME);
+                return Err(PgError::new(sqlstate::UNIQUE_VIOLATION, message).into())
+            }
+            table.push(row);
+        }
+        Ok((table.len() - len) as u64)
+    }
+
+    fn select(&self, query: &Select) -> Result<Vec<Vec<Value>>, QueryError> {
+        let filter = query.filter
+            .map(|filter| filter::Filter::parse(filter, query.tables))
+            .transpose()?;
+
+        let table_rows = |table: usize| self.tables.get(query.tables[table].name)
+            .map_or(&[][..], |rows| &**rows);
+        let mut joined: Vec<Joined> = table_rows(0).iter()
+            .map(|row| vec![Some(&**row)])
+            .collect();
+        for join in query.joins {
+            let mut next = vec![];
+            for row in joined {
+                let mut matched = false;
+                for other in table_rows(join.table) {
+                    let mut candidate = row.clone();
+                    candidate.push(Some(&**other));
+                    let (left, right) = join.on;
+                    let equal = compare(
+                        &column(query, &candidate, left),
+                        &column(query, &candidate, right),
+                    );
+                    if equal == Some(Ordering::Equal) {
+                        next.push(candidate);
+                        matched = true;
+                    }
+                }
+                if join.left && !matched {
+                    let mut candidate = row;
+                    candidate.push(None);
+                    next.push(candidate);
+                }
+            }
+            joined = next;
+        }
+
+        if let Some(filter) = &filter {
+            let mut kept = vec![];
+            for row in joined {
+                if filter.matches(&|c| column(query, &row, c))? {
+                    kept.push(row);
+                }
+            }
+            joined = kept;
+        }
+
+        let aggregated = query.columns.iter().any(|c| !matches!(c, Selected::Column(_)));
+        // each result along with a row it came from, which is what it's sorted
+        // and deduplicated on
+        let mut results: Vec<(Option<&Joined>, Vec<Value>)> = if aggregated || !query.group_by.is_empty() {
+            // groups in the order they're first seen
+            let mut groups: Vec<(Vec<Value>, Vec<&Joined>)> = vec![];
+            for row in &joined {
+                let key: Vec<_> = query.group_by.iter().map(|&c| column(query, row, c)).collect();
+                match groups.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, rows)) => rows.push(row),
+                    None => groups.push((key, vec![row])),
+                }
+            }
+            // aggregates without a `group by` always produce a row
+            if groups.is_empty() && query.group_by.is_empty() {
+                groups.push((vec![], vec![]));
+            }
+            // rows can only be sorted on grouped columns, which are the same
+            // for every row in the group
+            groups.into_iter()
+                .map(|(_, rows)| {
+                    let values = query.columns.iter()
+                        .map(|selected| aggregate(query, selected, &rows))
+                        .collect::<Result<_, _>>()?;
+                    Ok((rows.first().copied(), values))
+                })
+                .collect::<Result<_, QueryError>>()?
+        } else {
+            joined.iter()
+                .map(|row| {
+                    let values = query.columns.iter()
+                        .map(|selected| match selected {
+                            Selected::Column(c) => column(query, row, *c),
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    (Some(row), values)
+                })
+                .collect()
+        };
+
+        let key = |row: Option<&Joined>, c: ColumnRef| row.map_or(Value::Null, |row| column(query, row, c));
+        // `sort_by` is stable, so rows that sort the same stay in order
+        results.sort_by(|(a, _), (b, _)| {
+            query.order_by.iter()
+                .map(|order| {
+                    let ordering = sort_order(&key(*a, order.column), &key(*b, order.column));
+                    if order.descending { ordering.reverse() } else { ordering }
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        // keeps the first result for each key, NULLs being equal to each other
+        let mut seen = vec![];
+        match query.distinct {
+            Distinct::All => {},
+            Distinct::Rows => results.retain(|(_, values)| {
+                let new = !seen.contains(values);
+                if new {
+                    seen.push(values.clone());
+                }
+                new
+            }),
+            Distinct::On(on) => results.retain(|(row, _)| {
+                let values: Vec<_> = on.iter().map(|&c| key(*row, c)).collect();
+                let new = !seen.contains(&values);
+                if new {
+                    seen.push(values);
+                }
+                new
+            }),
+        }
+
+        let mut results: Vec<Vec<Value>> = results.into_iter().map(|(_, values)| values).collect();
+        if let Some(limit) = query.limit {
+            results.truncate(limit.max(0) as usize);
+        }
+        Ok(results)
+    }
+}
+
+impl Client for MemoryClient {
+    type Row = Vec<Value>;
+
+    fn before_query<T: PgTable>(&self) {}
+
+    fn select_rows<'c>(&'c self, query: &Select) -> Result<Rows<'c, Self::Row>, QueryError> {
+        Ok(Box::new(self.select(query)?.into_iter()))
+    }
+
+    fn insert_rows<T: PgWritableTable>(
+        &mut self,
+        rows: impl IntoIterator<Item = T>,
+        batch_size: usize,
+    ) -> Result<u64, QueryError> {
+        assert!(batch_size > 0, "INSERT batch size must be positive");
+        self.push_rows(rows)
+    }
+
+    // the tables are restored if `f` fails. Panics carry on unwinding
+    fn savepoint<R, E: From<PgError>>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let tables = self.tables.clone();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| f(self))) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(error)) => {
+                self.tables = tables;
+                Err(error)
+            },
+            Err(panic) => {
+                self.tables = tables;
+                std::panic::resume_unwind(panic)
+            },
+        }
+    }
+}
+
+fn column_index(columns: &[super::Column], name: &str) -> usize {
+    columns.iter()
+        .position(|column| column.name == name)
+        .unwrap_or_else(|| panic!("no column `{}`", name))
+}
+
+// the value of `c` in a joined row
+fn column(query: &Select, row: &[Option<&[Value]>], c: ColumnRef) -> Value {
+    match row[c.table] {
+        Some(values) => values[column_index(query.tables[c.table].columns, c.column)].clone(),
+        None => Value::Null,
+    }
+}
+
+fn aggregate(query: &Select, selected: &Selected, rows: &[&Joined]) -> Result<Value, QueryError> {
+    let values = |c: ColumnRef| rows.iter()
+        .map(move |row| column(query, row, c))
+        .filter(|value| *value != Value::Null);
+    let value = match *selected {
+        // plain columns are grouped on, so every row has the same value
+        Selected::Column(c) => rows.first().map_or(Value::Null, |row| column(query, row, c)),
+        Selected::Count(None) => Value::Int8(rows.len() as i64),
+        Selected::Count(Some(c)) => Value::Int8(values(c).count() as i64),
+        Selected::Min(c) => values(c)
+            .min_by(|a, b| compare(a, b).unwrap_or(Ordering::Equal))
+            .unwrap_or(Value::Null),
+        Selected::Max(c) => values(c)
+            .max_by(|a, b| compare(a, b).unwrap_or(Ordering::Equal))
+            .unwrap_or(Value::Null),
+        Selected::Sum(c) => return sum(values(c)),
+    };
+    Ok(value)
+}
+
+// the same result types as `PgSum`
+fn sum(values: impl Iterator<Item = Value>) -> Result<Value, QueryError> {
+    let not_a_number = |value: Value| -> QueryError {
+        PgError::new(sqlstate::DATATYPE_MISMATCH, format!("sum needs numbers, found {:?}", value)).into()
+    };
+    let mut values = values.peekable();
+    match values.peek() {
+        None => Ok(Value::Null),
+        Some(Value::Float4(_)) => values
+            .try_fold(0.0, |sum, value| match value {
+                Value::Float4(v) => Ok(sum + v),
+                value => Err(not_a_number(value)),
+            })
+            .map(Value::Float4),
+        Some(Value::Float8(_)) => values
+            .try_fold(0.0, |sum, value| match value {
+                Value::Float8(v) => Ok(sum + v),
+                value => Err(not_a_number(value)),
+            })
+            .map(Value::Float8),
+        Some(Value::Int8(_)) => values
+            .try_fold(0, |sum: i128, value| match value {
+                Value::Int8(v) => Ok(sum + i128::from(v)),
+                value => Err(not_a_number(value)),
+            })
+            .map(|sum| Value::Text(sum.to_string())),
+        Some(Value::Int2(_)) | Some(Value::Int4(_)) => values
+            .try_fold(0, |sum: i64, value| match value {
+                Value::Int2(v) => Ok(sum + i64::from(v)),
+                Value::Int4(v) => Ok(sum + i64::from(v)),
+                value => Err(not_a_number(value)),
+            })
+            .map(Value::Int8),
+        Some(_) => Err(not_a_number(values.next().unwrap())),
+    }
+}
+
+// postgres' default sort order, which puts NULLs after every other value
+fn sort_order(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Greater,
+        (_, Value::Null) => Ordering::Less,
+        _ => compare(a, b).unwrap_or(Ordering::Equal),
+    }
+}
+
+// SQL comparison, `None` if either side is NULL. Numbers compare across types
+pub(crate) fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    use Value::*;
+
+    let float = |value: &Value| match *value {
+        Float4(v) => Some(v.into()),
+        Float8(v) => Some(v),
+        _ => None,
+    };
+    let int = |value: &Value| match *value {
+        Int2(v) => Some(v.into()),
+        Int4(v) => Some(v.into()),
+        Int8(v) => Some(v),
+        Oid(v) => Some(v.into()),
+        _ => None,
+    };
+    match (a, b) {
+        (Null, _) | (_, Null) => None,
+        (Bool(a), Bool(b)) => Some(a.cmp(b)),
+        (Text(a), Text(b)) => Some(a.cmp(b)),
+        (Array(a), Array(b)) => {
+            for (a, b) in a.iter().zip(b) {
+                match compare(a, b)? {
+                    Ordering::Equal => continue,
+                    ordering => return Some(ordering),
+                }
+            }
+            Some(a.len().cmp(&b.len()))
+        },
+        _ => match (int(a), int(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => {
+                let a: f64 = float(a).or_else(|| int(a).map(|v| v as f64))?;
+                let b: f64 = float(b).or_else(|| int(b).map(|v| v as f64))?;
+                a.partial_cmp(&b)
+            },
+        },
+    }
+}
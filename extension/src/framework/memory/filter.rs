@@ -0,0 +1,401 @@
+// Evaluates `where:` strings for `MemoryClient`.
+//
+// Only a small subset of SQL is understood, enough for the conditions that are
+// typically written against our own tables:
+//  - columns, optionally qualified by their table's rust name
+//  - integer, decimal, 'string', `true`, `false` and `NULL` literals
+//  - `=`, `<>`, `!=`, `<`, `<=`, `>`, `>=`, `IS [NOT] NULL`
+//  - `[NOT] IN (...)` with a list of values, `[NOT] LIKE` and `[NOT] ILIKE`
+//  - `AND`, `OR`, `NOT` and parentheses
+// with SQL's three-valued logic, so that comparisons with NULL match nothing.
+// Anything else is returned as a `feature_not_supported` error, rather than
+// guessing at what postgres would do.
+
+use std::cmp::Ordering;
+
+use super::compare;
+use crate::framework::{
+    client::{ColumnRef, TableRef, Value},
+    error::{sqlstate, PgError, QueryError},
+};
+
+#[derive(Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Column(ColumnRef),
+    Literal(Value),
+    Compare(Box<Expr>, Comparison, Box<Expr>),
+    IsNull(Box<Expr>, bool),
+    // `expr [NOT] IN (values)`
+    In(Box<Expr>, Vec<Expr>, bool),
+    // `expr [NOT] LIKE pattern`, case-insensitively for `ILIKE`
+    Like { expr: Box<Expr>, pattern: Box<Expr>, negated: bool, case_insensitive: bool },
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(String),
+    Text(String),
+    Symbol(&'static str),
+}
+
+impl Filter {
+    // fails if `sql` isn't in the supported subset, or names an unknown column
+    pub fn parse(sql: &str, tables: &[TableRef]) -> Result<Self, QueryError> {
+        let mut parser = Parser { sql, tokens: vec![], next: 0, tables };
+        parser.tokens = tokenize(sql).map_err(|error| parser.unsupported(error))?;
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.unsupported(format!("unexpected {:?}", token)))
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, column: &dyn Fn(ColumnRef) -> Value) -> Result<bool, QueryError> {
+        match self.expr.eval(column)? {
+            Value::Bool(b) => Ok(b),
+            Value::Null => Ok(false),
+            value => Err(type_mismatch(format!("argument of WHERE must be a boolean, found {:?}", value))),
+        }
+    }
+}
+
+fn type_mismatch(message: String) -> QueryError {
+    PgError::new(sqlstate::DATATYPE_MISMATCH, message).into()
+}
+
+impl Expr {
+    fn eval(&self, column: &dyn Fn(ColumnRef) -> Value) -> Result<Value, QueryError> {
+        let truth = |expr: &Expr| match expr.eval(column)? {
+            Value::Bool(b) => Ok(Some(b)),
+            Value::Null => Ok(None),
+            value => Err(type_mismatch(format!("expected a boolean, found {:?}", value))),
+        };
+        let truth_value = |b: Option<bool>| b.map_or(Value::Null, Value::Bool);
+        let value = match self {
+            Expr::Column(c) => column(*c),
+            Expr::Literal(value) => value.clone(),
+            Expr::Compare(left, comparison, right) => {
+                let ordering = compare(&left.eval(column)?, &right.eval(column)?);
+                truth_value(ordering.map(|ordering| match comparison {
+                    Comparison::Eq => ordering == Ordering::Equal,
+                    Comparison::Ne => ordering != Ordering::Equal,
+                    Comparison::Lt => ordering == Ordering::Less,
+                    Comparison::Le => ordering != Ordering::Greater,
+                    Comparison::Gt => ordering == Ordering::Greater,
+                    Comparison::Ge => ordering != Ordering::Less,
+                }))
+            },
+            Expr::IsNull(expr, negated) => Value::Bool((expr.eval(column)? == Value::Null) != *negated),
+            Expr::In(expr, values, negated) => {
+                let value = expr.eval(column)?;
+                // true if any value is equal, otherwise unknown if any of the
+                // comparisons were
+                let mut found = Some(false);
+                for other in values {
+                    match compare(&value, &other.eval(column)?) {
+                        Some(Ordering::Equal) => {
+                            found = Some(true);
+                            break
+                        },
+                        Some(_) => {},
+                        None => found = None,
+                    }
+                }
+                truth_value(found.map(|found| found != *negated))
+            },
+            Expr::Like { expr, pattern, negated, case_insensitive } => {
+                match (expr.eval(column)?, pattern.eval(column)?) {
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (Value::Text(text), Value::Text(pattern)) => {
+                        let matched = if *case_insensitive {
+                            like(&text.to_lowercase(), &pattern.to_lowercase())
+                        } else {
+                            like(&text, &pattern)
+                        };
+                        Value::Bool(matched != *negated)
+                    },
+                    (text, pattern) => return Err(type_mismatch(format!(
+                        "LIKE needs text, found {:?} and {:?}", text, pattern,
+                    ))),
+                }
+            },
+            Expr::Not(expr) => truth_value(truth(expr)?.map(|b| !b)),
+            Expr::And(left, right) => truth_value(match (truth(left)?, truth(right)?) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }),
+            Expr::Or(left, right) => truth_value(match (truth(left)?, truth(right)?) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            }),
+        };
+        Ok(value)
+    }
+}
+
+// `%` matches any run of characters, `_` any one character, and `\` escapes
+// the character after it
+fn like(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    fn matches(text: &[char], pattern: &[char]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            ['%', rest @ ..] => (0..=text.len()).any(|skip| matches(&text[skip..], rest)),
+            ['_', rest @ ..] => !text.is_empty() && matches(&text[1..], rest),
+            ['\\', c, rest @ ..] | [c, rest @ ..] => {
+                text.first() == Some(c) && matches(&text[1..], rest)
+            },
+        }
+    }
+    matches(&text, &pattern)
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    const SYMBOLS: &[&str] = &["<>", "!=", "<=", ">=", "=", "<", ">", "(", ")", ",", "."];
+
+    let mut tokens = vec![];
+    let mut rest = sql.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..len].to_string()));
+            len
+        } else if c.is_ascii_digit() || (c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            let len = 1 + rest[1..].find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len() - 1);
+            tokens.push(Token::Number(rest[..len].to_string()));
+            len
+        } else if c == '\'' {
+            // `''` is an escaped quote
+            let mut text = String::new();
+            let mut chars = rest.char_indices().skip(1).peekable();
+            let end = loop {
+                match chars.next() {
+                    Some((_, '\'')) if matches!(chars.peek(), Some((_, '\''))) => {
+                        chars.next();
+                        text.push('\'');
+                    },
+                    Some((i, '\'')) => break i + 1,
+                    Some((_, c)) => text.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            };
+            tokens.push(Token::Text(text));
+            end
+        } else {
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                Some(symbol) => {
+                    tokens.push(Token::Symbol(symbol));
+                    symbol.len()
+                },
+                None => return Err(format!("unexpected `{}`", c)),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'t> {
+    sql: &'t str,
+    tokens: Vec<Token>,
+    next: usize,
+    tables: &'t [TableRef],
+}
+
+impl<'t> Parser<'t> {
+    fn unsupported(&self, error: impl std::fmt::Display) -> QueryError {
+        let message = format!("MemoryClient cannot evaluate `{}`: {}", self.sql, error);
+        PgError::new(sqlstate::FEATURE_NOT_SUPPORTED, message).into()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn next(&mut self) -> Result<Token, QueryError> {
+        let token = self.peek().cloned().ok_or_else(|| self.unsupported("unexpected end of input"))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    // consumes the keyword, case-insensitively, if it's next
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.next += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // consumes the symbol if it's next
+    fn symbol(&mut self, symbol: &'static str) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.next += 1;
+            return true
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)))
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, QueryError> {
+        let left = self.primary()?;
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            if !self.keyword("null") {
+                return Err(self.unsupported("expected `NULL` after `IS`"))
+            }
+            return Ok(Expr::IsNull(Box::new(left), negated))
+        }
+        // `NOT` can only be followed by `IN`, `LIKE` or `ILIKE` here
+        let start = self.next;
+        let negated = self.keyword("not");
+        if self.keyword("in") {
+            if !self.symbol("(") {
+                return Err(self.unsupported("expected `(` after `IN`"))
+            }
+            let mut values = vec![self.primary()?];
+            while self.symbol(",") {
+                values.push(self.primary()?);
+            }
+            if !self.symbol(")") {
+                return Err(self.unsupported("expected `)`"))
+            }
+            return Ok(Expr::In(Box::new(left), values, negated))
+        }
+        let case_insensitive = self.keyword("ilike");
+        if case_insensitive || self.keyword("like") {
+            let pattern = self.primary()?;
+            return Ok(Expr::Like { expr: Box::new(left), pattern: Box::new(pattern), negated, case_insensitive })
+        }
+        self.next = start;
+        let comparison = match self.peek() {
+            Some(Token::Symbol("=")) => Comparison::Eq,
+            Some(Token::Symbol("<>" | "!=")) => Comparison::Ne,
+            Some(Token::Symbol("<")) => Comparison::Lt,
+            Some(Token::Symbol("<=")) => Comparison::Le,
+            Some(Token::Symbol(">")) => Comparison::Gt,
+            Some(Token::Symbol(">=")) => Comparison::Ge,
+            _ => return Ok(left),
+        };
+        self.next += 1;
+        let right = self.primary()?;
+        Ok(Expr::Compare(Box::new(left), comparison, Box::new(right)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, QueryError> {
+        if self.symbol("(") {
+            let expr = self.or()?;
+            if !self.symbol(")") {
+                return Err(self.unsupported("expected `)`"))
+            }
+            return Ok(expr)
+        }
+        match self.next()? {
+            Token::Number(number) => {
+                let value = match number.parse() {
+                    Ok(int) => Value::Int8(int),
+                    Err(_) => Value::Float8(
+                        number.parse().map_err(|_| self.unsupported(format!("invalid number `{}`", number)))?,
+                    ),
+                };
+                Ok(Expr::Literal(value))
+            },
+            Token::Text(text) => Ok(Expr::Literal(Value::Text(text))),
+            Token::Word(word) if word.eq_ignore_ascii_case("true") => Ok(Expr::Literal(Value::Bool(true))),
+            Token::Word(word) if word.eq_ignore_ascii_case("false") => Ok(Expr::Literal(Value::Bool(false))),
+            Token::Word(word) if word.eq_ignore_ascii_case("null") => Ok(Expr::Literal(Value::Null)),
+            Token::Word(word) => {
+                if self.symbol(".") {
+                    let column = match self.next()? {
+                        Token::Word(column) => column,
+                        token => return Err(self.unsupported(format!("expected a column, found {:?}", token))),
+                    };
+                    return self.column(Some(&word), &column)
+                }
+                self.column(None, &word)
+            },
+            token => Err(self.unsupported(format!("unexpected {:?}", token))),
+        }
+    }
+
+    // fails with the errors postgres would give for an unknown or ambiguous column
+    fn column(&self, table: Option<&str>, column: &str) -> Result<Expr, QueryError> {
+        let mut found = self.tables.iter().enumerate()
+            .filter(|(_, t)| match table {
+                Some(table) => t.alias == table,
+                None => true,
+            })
+            .filter(|(_, t)| t.columns.iter().any(|c| c.name == column))
+            .map(|(table, _)| ColumnRef { table, column: self.column_name(table, column) });
+        match (found.next(), found.next()) {
+            (Some(c), None) => Ok(Expr::Column(c)),
+            (Some(_), Some(_)) => Err(PgError::new(
+                sqlstate::AMBIGUOUS_COLUMN,
+                format!("column reference \"{}\" is ambiguous", column),
+            ).into()),
+            (None, _) => {
+                let name = match table {
+                    Some(table) => format!("{}.{}", table, column),
+                    None => column.to_string(),
+                };
+                let message = format!("column \"{}\" does not exist", name);
+                Err(PgError::new(sqlstate::UNDEFINED_COLUMN, message).into())
+            },
+        }
+    }
+
+    // the column's name with the `'static` lifetime `ColumnRef` needs
+    fn column_name(&self, table: usize, column: &str) -> &'static str {
+        self.tables[table].columns.iter()
+            .find(|c| c.name == column)
+            .map(|c| c.name)
+            .unwrap()
+    }
+}
@@ -0,0 +1,280 @@
+// Checks of the layout of tables, as declared in rust, against the layout
+// recorded in the catalog.
+
+use std::fmt;
+
+use pgx::*;
+
+use super::{PgTable, TableDefinition};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDiff {
+    pub table: &'static str,
+    pub mismatches: Vec<SchemaMismatch>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaMismatch {
+    MissingTable,
+    MissingColumn {
+        column: &'static str,
+    },
+    WrongType {
+        column: &'static str,
+        expected: &'static str,
+        actual: String,
+    },
+    // the column is declared as non-`Option` but the table allows NULLs in it
+    Nullable {
+        column: &'static str,
+    },
+    // the column is declared as an `Option` but the table is `NOT NULL`
+    NotNull {
+        column: &'static str,
+    },
+    ExtraColumn {
+        column: String,
+    },
+    // positions are 1-based, as in `pg_attribute`, ignoring dropped columns
+    WrongPosition {
+        column: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    UnexpectedConstraint {
+        name: String,
+        definition: String,
+    },
+    MissingConstraint {
+        definition: String,
+    },
+}
+
+// a column as described by `pg_attribute`
+struct CatalogColumn {
+    name: String,
+    sql_type: String,
+    not_null: bool,
+}
+
+// tables created by `table!` must match their declaration exactly: the same
+// columns, in the same order, with the same types and nullability, and with the
+// declared primary key as the only constraint
+pub fn verify_schema<T: PgTable>(client: &SpiClient) -> Result<(), SchemaDiff> {
+    compare::<T>(client, true)
+}
+
+// tables we don't own only need to have the columns we read, with the types we
+// expect; they may have other columns, in any order
+pub fn check_extern_table<T: PgTable>(client: &SpiClient) -> Result<(), SchemaDiff> {
+    compare::<T>(client, false)
+}
+
+fn compare<T: PgTable>(client: &SpiClient, exact: bool) -> Result<(), SchemaDiff> {
+    use SchemaMismatch::*;
+
+    let mut diff = SchemaDiff { table: T::NAME, mismatches: vec![] };
+    let columns = match catalog_columns(client, T::NAME) {
+        Some(columns) => columns,
+        None => {
+            diff.mismatches.push(MissingTable);
+            return Err(diff)
+        },
+    };
+
+    for expected in T::COLUMNS {
+        let actual = match columns.iter().find(|column| column.name == expected.name) {
+            Some(actual) => actual,
+            None => {
+                diff.mismatches.push(MissingColumn { column: expected.name });
+                continue
+            },
+        };
+        if actual.sql_type != expected.sql_type {
+            diff.mismatches.push(WrongType {
+                column: expected.name,
+                expected: expected.sql_type,
+                actual: actual.sql_type.clone(),
+            });
+        }
+        if !expected.nullable && !actual.not_null {
+            diff.mismatches.push(Nullable { column: expected.name });
+        }
+        if exact && expected.nullable && actual.not_null {
+            diff.mismatches.push(NotNull { column: expected.name });
+        }
+    }
+
+    if exact {
+        let extra = columns.iter()
+            .filter(|actual| T::COLUMNS.iter().all(|expected| expected.name != actual.name));
+        for actual in extra {
+            diff.mismatches.push(ExtraColumn { column: actual.name.clone() });
+        }
+
+        // with missing or extra columns everything after them will be out of
+        // place, so only report the order when it's the only difference
+        let same_columns = !diff.mismatches.iter()
+            .any(|mismatch| matches!(mismatch, MissingColumn { .. } | ExtraColumn { .. }));
+        if same_columns {
+            for (expected_position, expected) in T::COLUMNS.iter().enumerate() {
+                let actual_position = columns.iter()
+                    .position(|actual| actual.name == expected.name)
+                    .unwrap();
+                if actual_position != expected_position {
+                    diff.mismatches.push(WrongPosition {
+                        column: expected.name,
+                        expected: expected_position + 1,
+                        actual: actual_position + 1,
+                    });
+                }
+            }
+        }
+
+        let mut key = (!T::KEY.is_empty())
+            .then(|| format!("PRIMARY KEY ({})", T::KEY.join(", ")));
+        for (name, definition) in catalog_constraints(client, T::NAME) {
+            if key.as_ref() == Some(&definition) {
+                key = None;
+                continue
+            }
+            diff.mismatches.push(UnexpectedConstraint { name, definition });
+        }
+        if let Some(definition) = key {
+            diff.mismatches.push(MissingConstraint { definition });
+        }
+    }
+
+    if diff.mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(diff)
+    }
+}
+
+fn table_arg(table: &str) -> Option<Vec<(PgOid, Option<pg_sys::Datum>)>> {
+    Some(vec![(PgOid::BuiltIn(PgBuiltInOids::TEXTOID), table.into_datum())])
+}
+
+// the table's live columns in order, or `None` if the table doesn't exist
+fn catalog_columns(client: &SpiClient, table: &str) -> Option<Vec<CatalogColumn>> {
+    let exists: Option<bool> = client
+        .select("SELECT to_regclass($1) IS NOT NULL", None, table_arg(table))
+        .next()
+        .and_then(|row| row.by_ordinal(1).unwrap().value());
+    if exists != Some(true) {
+        return None
+    }
+
+    let columns = client
+        .select(
+            "SELECT attname::text, format_type(atttypid, NULL), attnotnull \
+            FROM pg_catalog.pg_attribute \
+            WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped \
+            ORDER BY attnum",
+            None,
+            table_arg(table),
+        )
+        .map(|row| CatalogColumn {
+            name: row.by_ordinal(1).unwrap().value().unwrap(),
+            sql_type: row.by_ordinal(2).unwrap().value().unwrap(),
+            not_null: row.by_ordinal(3).unwrap().value().unwrap(),
+        })
+        .collect();
+    Some(columns)
+}
+
+// the names and definitions of the table's constraints
+fn catalog_constraints(client: &SpiClient, table: &str) -> Vec<(String, String)> {
+    client
+        .select(
+            "SELECT conname::text, pg_get_constraintdef(oid) \
+            FROM pg_catalog.pg_constraint \
+            WHERE conrelid = to_regclass($1) \
+            ORDER BY conname",
+            None,
+            table_arg(table),
+        )
+        .map(|row| (
+            row.by_ordinal(1).unwrap().value().unwrap(),
+            row.by_ordinal(2).unwrap().value().unwrap(),
+        ))
+        .collect()
+}
+
+// the tables the extension created that aren't among `tables`. A table left
+// out of `TABLES` would be silently left out of the schema lock and the upgrade
+// scripts too. The shadow tables holding seed rows for `pg_dump` don't count
+pub fn unlisted_tables(client: &SpiClient, tables: &[TableDefinition]) -> Vec<String> {
+    let names: Vec<String> = tables.iter().map(|table| table.name.to_string()).collect();
+    client
+        .select(
+            "SELECT c.oid::regclass::text \
+            FROM pg_catalog.pg_depend d \
+            JOIN pg_catalog.pg_class c ON c.oid = d.objid \
+            JOIN pg_catalog.pg_extension e ON e.oid = d.refobjid \
+            WHERE d.classid = 'pg_catalog.pg_class'::regclass \
+                AND d.refclassid = 'pg_catalog.pg_extension'::regclass \
+                AND d.deptype = 'e' \
+                AND e.extname = 'table_builder' \
+                AND c.relkind = 'r' \
+                AND c.relname NOT LIKE '\\_\\_table\\_builder\\_seed\\_%' \
+                AND NOT EXISTS ( \
+                    SELECT FROM unnest($1::text[]) AS listed(name) \
+                    WHERE to_regclass(listed.name) = c.oid \
+                ) \
+            ORDER BY 1",
+            None,
+            Some(vec![(PgOid::BuiltIn(PgBuiltInOids::TEXTARRAYOID), names.into_datum())]),
+        )
+        .map(|row| row.by_ordinal(1).unwrap().value().unwrap())
+        .collect()
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "table \"{}\" does not match its definition", self.table)?;
+        for (i, mismatch) in self.mismatches.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            write!(f, "{}{}", sep, mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SchemaMismatch::*;
+        match self {
+            MissingTable => write!(f, "the table does not exist"),
+            MissingColumn { column } => write!(f, "column \"{}\" does not exist", column),
+            WrongType { column, expected, actual } => write!(
+                f,
+                "column \"{}\" is of type {} instead of {}",
+                column, actual, expected,
+            ),
+            Nullable { column } => write!(
+                f,
+                "column \"{}\" is nullable but declared as non-Option",
+                column,
+            ),
+            NotNull { column } => write!(
+                f,
+                "column \"{}\" is NOT NULL but declared as an Option",
+                column,
+            ),
+            ExtraColumn { column } => write!(f, "unexpected column \"{}\"", column),
+            WrongPosition { column, expected, actual } => write!(
+                f,
+                "column \"{}\" is at position {} instead of {}",
+                column, actual, expected,
+            ),
+            UnexpectedConstraint { name, definition } => write!(
+                f,
+                "unexpected constraint \"{}\" {}",
+                name, definition,
+            ),
+            MissingConstraint { definition } => write!(f, "missing constraint {}", definition),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// A checked-in record of the extension's tables as of its current version, so
+// that changing a table without bumping the version, or without a way to
+// upgrade existing installs, is caught before it ships.
+
+use std::{collections::BTreeMap, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{upgrade, TableDefinition};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: String,
+    pub tables: BTreeMap<String, TableSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    pub columns: Vec<ColumnSnapshot>,
+    pub ddl: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnSnapshot {
+    pub name: String,
+    pub sql_type: String,
+    pub nullable: bool,
+}
+
+impl Snapshot {
+    pub fn current(version: &str, tables: &[TableDefinition]) -> Self {
+        let tables = tables.iter()
+            .map(|table| {
+                let columns = table.columns.iter()
+                    .map(|column| ColumnSnapshot {
+                        name: column.name.to_string(),
+                        sql_type: column.sql_type.to_string(),
+                        nullable: column.nullable,
+                    })
+                    .collect();
+                (table.name.to_string(), TableSnapshot { columns, ddl: table.create.to_string() })
+            })
+            .collect();
+        Self { version: version.to_string(), tables }
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        serde_json::from_reader(file).map_err(io::Error::from)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        std::fs::write(path, json)
+    }
+}
+
+// compare the snapshot of the last released layout with the current one. Every
+// difference needs the extension version to have been bumped, an upgrade
+// script from the old version to the new one, and a history for the changed
+// tables covering the versions in between. Once all of that is in place, the
+// snapshot itself needs to be updated to the new version
+pub fn check(
+    locked: &Snapshot,
+    current: &Snapshot,
+    tables: &[TableDefinition],
+    upgrade_script: &Path,
+) -> Result<(), Vec<String>> {
+    if locked == current {
+        return Ok(())
+    }
+
+    let mut errors = vec![];
+    let changed: Vec<&str> = locked.tables.keys()
+        .chain(current.tables.keys())
+        .filter(|name| locked.tables.get(*name) != current.tables.get(*name))
+        .map(|name| &**name)
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let bumped = upgrade::version_key(&current.version) > upgrade::version_key(&locked.version);
+    if !changed.is_empty() && !bumped {
+        for table in &changed {
+            errors.push(format!(
+                "table `{}` changed but the extension version is still {}",
+                table, current.version,
+            ));
+        }
+        return Err(errors)
+    }
+
+    if !changed.is_empty() {
+        if !upgrade_script.exists() {
+            errors.push(format!(
+                "no upgrade script from {} to {} at {}",
+                locked.version, current.version, upgrade_script.display(),
+            ));
+        }
+        for table in tables.iter().filter(|table| changed.contains(&table.name)) {
+            let history = upgrade::upgrade_script(&locked.version, &current.version, &[table.history]);
+            if history.is_empty() {
+                errors.push(format!(
+                    "table `{}` changed but has no history for versions after {}",
+                    table.name, locked.version,
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        errors.push(format!(
+            "the schema snapshot is for version {} but the extension is at {}, it needs updating",
+            locked.version, current.version,
+        ));
+    }
+    Err(errors)
+}
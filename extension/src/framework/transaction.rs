@@ -0,0 +1,88 @@
+// Savepoints, so that a unit of work can fail without aborting the whole
+// transaction.
+//
+// A savepoint runs its closure in a subtransaction, like PL/pgSQL's
+// `BEGIN ... EXCEPTION` blocks. Errors postgres raises inside it reach rust as
+// a panic; we catch those, roll the subtransaction back and return the error as
+// a `PgError`, leaving the transaction usable. The subtransaction is also
+// rolled back if the closure returns an error, or panics for any other reason,
+// in which case the panic carries on unwinding.
+
+use std::panic::AssertUnwindSafe;
+
+use pgx::pg_sys;
+
+use super::error::PgError;
+
+pub fn savepoint<R, E: From<PgError>>(f: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    let subtransaction = Subtransaction::begin();
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => {
+            subtransaction.commit();
+            Ok(value)
+        },
+        Ok(Err(error)) => {
+            subtransaction.rollback();
+            Err(error)
+        },
+        // pgx's guard turns the longjmp of a postgres error into a panic
+        // carrying a `JumpContext`, only then is there error data to take
+        Err(panic) if panic.is::<pg_sys::JumpContext>() => {
+            Err(subtransaction.rollback_error().into())
+        },
+        Err(panic) => {
+            subtransaction.rollback();
+            std::panic::resume_unwind(panic)
+        },
+    }
+}
+
+// the state to return to once the subtransaction ends
+struct Subtransaction {
+    memory_context: pg_sys::MemoryContext,
+    resource_owner: pg_sys::ResourceOwner,
+}
+
+impl Subtransaction {
+    fn begin() -> Self {
+        unsafe {
+            let memory_context = pg_sys::CurrentMemoryContext;
+            let resource_owner = pg_sys::CurrentResourceOwner;
+            pg_sys::BeginInternalSubTransaction(std::ptr::null());
+            // keep allocating in the caller's context, so that anything
+            // returned from the savepoint outlives it
+            pg_sys::CurrentMemoryContext = memory_context;
+            Self { memory_context, resource_owner }
+        }
+    }
+
+    fn commit(self) {
+        unsafe {
+            pg_sys::ReleaseCurrentSubTransaction();
+            self.restore();
+        }
+    }
+
+    fn rollback(self) {
+        unsafe {
+            pg_sys::RollbackAndReleaseCurrentSubTransaction();
+            self.restore();
+        }
+    }
+
+    // rolls back after postgres raised an error, returning it
+    fn rollback_error(self) -> PgError {
+        unsafe {
+            // postgres is still in `ErrorContext`
+            pg_sys::CurrentMemoryContext = self.memory_context;
+            let error = PgError::take();
+            self.rollback();
+            error
+        }
+    }
+
+    unsafe fn restore(&self) {
+        pg_sys::CurrentMemoryContext = self.memory_context;
+        pg_sys::CurrentResourceOwner = self.resource_owner;
+    }
+}
@@ -0,0 +1,49 @@
+// Builds `ALTER EXTENSION ... UPDATE` scripts out of the version history
+// `table!` records for each table: when the table was created, and when its
+// columns were added, changed, and dropped.
+
+use super::TableDefinition;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub version: &'static str,
+    pub sql: &'static str,
+}
+
+// the statements needed to go from version `from` to version `to`, that is
+// every change made after `from` up to and including `to`, in version order.
+// Changes to different tables in the same version are applied in the order the
+// tables are given in
+pub fn upgrade_script(from: &str, to: &str, histories: &[&[Change]]) -> String {
+    let (from, to) = (version_key(from), version_key(to));
+    let mut changes: Vec<_> = histories.iter()
+        .flat_map(|history| history.iter())
+        .map(|change| (version_key(change.version), change))
+        .filter(|(version, _)| *version > from && *version <= to)
+        .collect();
+    changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut script = String::new();
+    for (_, change) in changes {
+        script.push_str(change.sql);
+    }
+    script
+}
+
+// the whole upgrade script for the extension: the changes to `tables`, then
+// their seed rows, which are only recorded when they can be re-applied
+pub fn extension_upgrade_script(from: &str, to: &str, tables: &[TableDefinition]) -> String {
+    let histories: Vec<_> = tables.iter().map(|table| table.history).collect();
+    let mut script = upgrade_script(from, to, &histories);
+    for table in tables {
+        script.push_str(table.seed);
+    }
+    script
+}
+
+// versions are compared component-wise, so that 1.10 comes after 1.9
+pub(crate) fn version_key(version: &str) -> Vec<u64> {
+    version.split('.')
+        .map(|part| part.parse().unwrap_or_else(|_| panic!("invalid version `{}`", version)))
+        .collect()
+}
@@ -27,6 +27,23 @@ table!{
     }
 }
 
+// every table owned by the extension, for generating upgrade scripts and the
+// schema snapshot. Tables need to be listed in the order they can be created in
+pub const TABLES: &[framework::TableDefinition] = &[
+    framework::TableDefinition::of::<ExampleTable>(),
+    framework::TableDefinition::of::<InsertExample>(),
+];
+
+// the `default_version` from the control file
+pub fn extension_version() -> &'static str {
+    include_str!("../table_builder.control")
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == "default_version")
+        .map(|(_, version)| version.trim().trim_matches('\''))
+        .expect("the control file has no default_version")
+}
+
 #[cfg(any(test, feature = "pg_test"))]
 #[pg_schema]
 mod tests {
@@ -38,6 +55,26 @@ mod tests {
 
     use crate::framework;
 
+    // the tables created by the tests, which aren't part of `crate::TABLES`
+    const TEST_TABLES: &[framework::TableDefinition] = &[
+        framework::TableDefinition::of::<KeyValueTable>(),
+        framework::TableDefinition::of::<BatchTable>(),
+        framework::TableDefinition::of::<CopyTable>(),
+        framework::TableDefinition::of::<JobQueue>(),
+        framework::TableDefinition::of::<VersionTable>(),
+        framework::TableDefinition::of::<Author>(),
+        framework::TableDefinition::of::<Book>(),
+        framework::TableDefinition::of::<VerifiedTable>(),
+        framework::TableDefinition::of::<VersionedTable>(),
+        framework::TableDefinition::of::<DumpedTable>(),
+        framework::TableDefinition::of::<Unit>(),
+        framework::TableDefinition::of::<Currency>(),
+        framework::TableDefinition::of::<AttributeTable>(),
+        framework::TableDefinition::of::<DocumentedTable>(),
+        framework::TableDefinition::of::<Account>(),
+        framework::TableDefinition::of::<DriftingTable>(),
+    ];
+
     table!{
         KeyValueTable (
             key: String,
@@ -64,7 +101,7 @@ mod tests {
                     key: "111".to_string(),
                     value: Some(6),
                 }
-            );
+            ).unwrap();
 
             query!(client
                 insert into: KeyValueTable
@@ -72,7 +109,7 @@ mod tests {
                     key: key.clone(),
                     value: value.clone(),
                 })
-            );
+            ).unwrap();
 
             for i in 1000..1010 {
                 expected.insert(i.to_string(), Some(i));
@@ -82,7 +119,7 @@ mod tests {
                 from: KeyValueTable
                 select: (key, value)
                 where: "key <> 'foo'"
-            );
+            ).unwrap();
             let mut count = 0;
             for (key, val) in values {
                 if key == "111" {
@@ -96,6 +133,882 @@ mod tests {
             Ok(Some(()))
         });
     }
+
+    table!{
+        BatchTable (
+            id: i32,
+            label: String,
+        )
+    }
+
+    #[pg_test]
+    fn test_batched_insert() {
+        Spi::connect(|mut client| {
+            // enough rows to need several statements, with a partial last batch
+            let inserted = query!(client
+                insert into: BatchTable
+                values: (0..2500).map(|id| BatchTable { id, label: id.to_string() })
+                batch_size: 1000
+            ).unwrap();
+            assert_eq!(inserted, 2500);
+
+            let mut count = 0;
+            for (id, label) in query!(client from: BatchTable select: (id, label)).unwrap() {
+                assert_eq!(label, id.to_string());
+                count += 1;
+            }
+            assert_eq!(count, 2500);
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        CopyTable (
+            id: i64,
+            name: Option<String>,
+            weight: f64,
+        )
+    }
+
+    #[pg_test]
+    fn test_copy_in() {
+        let copied = CopyTable::copy_in((0..10_000).map(|id| CopyTable {
+            id,
+            name: (id % 2 == 0).then(|| format!("row {}", id)),
+            weight: id as f64 / 2.0,
+        }));
+        assert_eq!(copied, 10_000);
+
+        Spi::connect(|client| {
+            let mut count = 0;
+            for (id, name, weight) in query!(client from: CopyTable select: (id, name, weight)).unwrap() {
+                assert_eq!(name, (id % 2 == 0).then(|| format!("row {}", id)));
+                assert_eq!(weight, id as f64 / 2.0);
+                count += 1;
+            }
+            assert_eq!(count, 10_000);
+            Ok(Some(()))
+        });
+    }
+
+    #[pg_test]
+    fn test_copy_in_row_level_security() {
+        use crate::framework::{error::{sqlstate, PgError}, transaction::savepoint};
+
+        let schema = Spi::get_one::<String>("\
+            SELECT extnamespace::regnamespace::text \
+            FROM pg_extension \
+            WHERE extname = 'table_builder'"
+        ).unwrap();
+        Spi::run("ALTER TABLE CopyTable ENABLE ROW LEVEL SECURITY");
+        Spi::run("CREATE POLICY even_rows ON CopyTable USING (id % 2 = 0)");
+        // superusers and the table's owner aren't subject to the policies
+        Spi::run("CREATE ROLE table_builder_copier");
+        Spi::run(&format!("GRANT USAGE ON SCHEMA {} TO table_builder_copier", schema));
+        Spi::run("GRANT INSERT ON CopyTable TO table_builder_copier");
+        Spi::run("SET LOCAL ROLE table_builder_copier");
+
+        let copied = savepoint(|| Ok::<_, PgError>(CopyTable::copy_in((0..3).map(|id| CopyTable {
+            id,
+            name: None,
+            weight: 0.0,
+        }))));
+        let error = copied.unwrap_err();
+        assert_eq!(error.code, sqlstate::FEATURE_NOT_SUPPORTED);
+        assert_eq!(error.message, "COPY FROM not supported with row-level security");
+
+        Spi::run("RESET ROLE");
+        assert_eq!(Spi::get_one::<i64>("SELECT count(*) FROM CopyTable"), Some(0));
+    }
+
+    #[pg_test]
+    fn test_streaming_select() {
+        CopyTable::copy_in((0..2_500).map(|id| CopyTable { id, name: None, weight: 0.0 }));
+
+        Spi::connect(|client| {
+            let rows = query!(client
+                from: CopyTable
+                select: (id, name)
+                where: "id >= 500"
+                fetch_size: 100
+            ).unwrap();
+            let mut ids: Vec<i64> = rows.map(|(id, name)| {
+                assert_eq!(name, None);
+                id
+            }).collect();
+            ids.sort_unstable();
+            assert_eq!(ids, (500..2_500).collect::<Vec<_>>());
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        JobQueue (
+            id: i32,
+            claimed: bool,
+        )
+        insert: {
+            (0..10).map(|id| (id, false))
+        }
+    }
+
+    #[pg_test]
+    fn test_locking_select() {
+        Spi::connect(|mut client| {
+            let claimed: Vec<i32> = query!(client
+                from: JobQueue
+                select: (id)
+                where: "NOT claimed"
+                limit: 3
+                lock: for update skip locked
+            ).unwrap().collect();
+            assert_eq!(claimed.len(), 3);
+
+            // a row lock stores the locking transaction's id in the row's xmax
+            let locked = client.select(
+                "SELECT count(*) FROM JobQueue WHERE xmax::text::bigint = txid_current() % 4294967296",
+                None,
+                None,
+            ).first().get_one::<i64>();
+            assert_eq!(locked, Some(3));
+            Ok(Some(()))
+        });
+    }
+
+    // runs without postgres
+    #[test]
+    fn test_memory_client() {
+        use framework::{client::Value, memory::MemoryClient};
+
+        let mut client = MemoryClient::new();
+        query!(client
+            insert into: JobQueue
+            values: (0..5).map(|id| JobQueue { id, claimed: id % 2 == 0 })
+        ).unwrap();
+        let unclaimed: Vec<i32> = query!(client
+            from: JobQueue
+            select: (id)
+            where: "NOT claimed AND id > 1"
+        ).unwrap().collect();
+        assert_eq!(unclaimed, vec![3]);
+
+        let (count, max): (i64, Option<i32>) = query!(client
+            from: JobQueue
+            select: (count(*), max(id))
+        ).unwrap().next().unwrap();
+        assert_eq!((count, max), (5, Some(4)));
+
+        let listed: Vec<i32> = query!(client
+            from: JobQueue
+            select: (id)
+            where: "id IN (1, 3, 7) AND NOT id NOT IN (3)"
+        ).unwrap().collect();
+        assert_eq!(listed, vec![3]);
+
+        // SQL the client doesn't understand is an error rather than a panic
+        let unsupported = query!(client
+            from: JobQueue
+            select: (id)
+            where: "id % 2 = 0"
+        ).err().unwrap();
+        assert_eq!(unsupported.code(), framework::error::sqlstate::FEATURE_NOT_SUPPORTED);
+
+        assert_eq!(client.rows::<JobQueue>()[1], vec![Value::Int4(1), Value::Bool(false)]);
+
+        assert_eq!(id_sum(&client), None);
+        query!(client
+            insert into: CopyTable
+            values: [i64::MAX, 1].iter().map(|&id| CopyTable { id, name: None, weight: 0.0 })
+        ).unwrap();
+        assert_eq!(id_sum(&client), Some("9223372036854775808".to_string()));
+    }
+
+    #[pg_test]
+    fn test_single_row() {
+        use crate::framework::error::QueryError;
+
+        Spi::connect(|mut client| {
+            let value = query!(client
+                from: KeyValueTable
+                select_one: (value)
+                where: "key = '1003'"
+            ).unwrap();
+            assert_eq!(value, Some(1003));
+
+            let missing = query!(client
+                from: KeyValueTable
+                select_one: (value)
+                where: "key = 'missing'"
+            ).err().unwrap();
+            assert!(matches!(missing, QueryError::NotFound(_)));
+            let many = query!(client
+                from: KeyValueTable
+                select_optional: (key)
+            ).err().unwrap();
+            assert!(matches!(many, QueryError::TooMany(_)));
+
+            let key = query!(client
+                from: KeyValueTable
+                select_optional: (key)
+                where: "key = 'missing'"
+            ).unwrap();
+            assert_eq!(key, None);
+
+            assert!(query!(client exists: KeyValueTable where: "value > 1005").unwrap());
+            assert!(!query!(client exists: KeyValueTable where: "value > 2000").unwrap());
+
+            let inserted = query!(client
+                insert into: KeyValueTable
+                value: KeyValueTable { key: "2000".to_string(), value: None }
+            ).unwrap();
+            assert_eq!(inserted, 1);
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        VersionTable (
+            key: String,
+            version: i32,
+            size: Option<i16>,
+        )
+        insert: {
+            [("a", 1, Some(1)), ("a", 3, None), ("b", 2, Some(5))]
+                .into_iter()
+                .map(|(key, version, size)| (key.to_string(), version, size))
+        }
+    }
+
+    #[pg_test]
+    fn test_aggregates() {
+        Spi::connect(|client| {
+            let (count, max): (i64, Option<i32>) = query!(client
+                from: VersionTable
+                select: (count(*), max(version))
+            ).unwrap().next().unwrap();
+            assert_eq!(count, 3);
+            assert_eq!(max, Some(3));
+
+            let mut per_key: Vec<(String, Option<i32>, i64, Option<i64>)> = query!(client
+                from: VersionTable
+                select: (key, max(version), count(size), sum(size))
+                group by: (key)
+            ).unwrap().collect();
+            per_key.sort();
+            assert_eq!(per_key, vec![
+                ("a".to_string(), Some(3), 1, Some(1)),
+                ("b".to_string(), Some(2), 1, Some(5)),
+            ]);
+
+            let (empty_count, empty_max) = query!(client
+                from: VersionTable
+                select: (count(*), max(version))
+                where: "key = 'missing'"
+            ).unwrap().next().unwrap();
+            assert_eq!(empty_count, 0);
+            assert_eq!(empty_max, None);
+
+            // bigints are summed into a numeric
+            CopyTable::copy_in([i64::MAX, 1].iter().map(|&id| CopyTable { id, name: None, weight: 0.0 }));
+            assert_eq!(id_sum(&client), Some("9223372036854775808".to_string()));
+            Ok(Some(()))
+        });
+    }
+
+    // the same queries against postgres and the in-memory client
+    fn id_sum(client: &impl framework::client::Client) -> Option<String> {
+        query!(client
+            from: CopyTable
+            select: (sum(id))
+        ).unwrap().next().unwrap()
+    }
+
+    fn latest_versions(client: &impl framework::client::Client) -> Vec<(String, i32)> {
+        query!(client
+            from: VersionTable
+            select: (key, version)
+            distinct on: (key)
+            order by: (key, version desc)
+        ).unwrap().collect()
+    }
+
+    fn distinct_keys(client: &impl framework::client::Client) -> Vec<String> {
+        query!(client
+            from: VersionTable
+            select: (key)
+            distinct
+            order by: (key desc)
+        ).unwrap().collect()
+    }
+
+    #[pg_test]
+    fn test_distinct() {
+        Spi::connect(|client| {
+            assert_eq!(latest_versions(&client), vec![("a".to_string(), 3), ("b".to_string(), 2)]);
+            assert_eq!(distinct_keys(&client), vec!["b".to_string(), "a".to_string()]);
+            Ok(Some(()))
+        });
+
+        let client = framework::memory::MemoryClient::new().with_rows(
+            [("a", 1, Some(1)), ("a", 3, None), ("b", 2, Some(5))]
+                .into_iter()
+                .map(|(key, version, size)| VersionTable { key: key.to_string(), version, size })
+        );
+        assert_eq!(latest_versions(&client), vec![("a".to_string(), 3), ("b".to_string(), 2)]);
+        assert_eq!(distinct_keys(&client), vec!["b".to_string(), "a".to_string()]);
+    }
+
+    table!{
+        Author (
+            id: i32,
+            name: String,
+        )
+        insert: {
+            [(1, "ann"), (2, "bob")].into_iter().map(|(id, name)| (id, name.to_string()))
+        }
+    }
+
+    table!{
+        Book (
+            author_id: i32,
+            title: String,
+        )
+        insert: {
+            [(1, "first"), (1, "second")].into_iter().map(|(id, title)| (id, title.to_string()))
+        }
+    }
+
+    #[pg_test]
+    fn test_joins() {
+        Spi::connect(|client| {
+            let mut books: Vec<(String, String)> = query!(client
+                from: Author join Book on Author.id = Book.author_id
+                select: (Author.name, Book.title)
+            ).unwrap().collect();
+            books.sort();
+            assert_eq!(books, vec![
+                ("ann".to_string(), "first".to_string()),
+                ("ann".to_string(), "second".to_string()),
+            ]);
+
+            // columns from the right side of a left join may be missing
+            let mut books: Vec<(String, Option<String>)> = query!(client
+                from: Author left join Book on Author.id = Book.author_id
+                select: (Author.name, Book.title)
+            ).unwrap().collect();
+            books.sort();
+            assert_eq!(books, vec![
+                ("ann".to_string(), Some("first".to_string())),
+                ("ann".to_string(), Some("second".to_string())),
+                ("bob".to_string(), None),
+            ]);
+
+            let mut counts: Vec<(String, i64)> = query!(client
+                from: Author left join Book on Author.id = Book.author_id
+                select: (Author.name, count(Book.title))
+                group by: (Author.name)
+            ).unwrap().collect();
+            counts.sort();
+            assert_eq!(counts, vec![("ann".to_string(), 2), ("bob".to_string(), 0)]);
+            Ok(Some(()))
+        });
+    }
+
+    #[pg_test]
+    fn test_catalogs() {
+        use crate::framework::catalog::*;
+
+        Spi::connect(|client| {
+            let mut columns: Vec<(String, i16, bool)> = query!(client
+                from: pg_class join pg_attribute on pg_class.oid = pg_attribute.attrelid
+                select: (pg_attribute.attname, pg_attribute.attnum, pg_attribute.attnotnull)
+                where: "pg_class.relname = 'author' AND pg_attribute.attnum > 0"
+            ).unwrap().collect();
+            columns.sort_by_key(|&(_, attnum, _)| attnum);
+            assert_eq!(columns, vec![
+                ("id".to_string(), 1, true),
+                ("name".to_string(), 2, true),
+            ]);
+
+            let (kind, namespace) = query!(client
+                from: pg_class join pg_namespace on pg_class.relnamespace = pg_namespace.oid
+                select: (pg_class.relkind, pg_namespace.nspname)
+                where: "pg_class.relname = 'pg_class'"
+            ).unwrap().next().unwrap();
+            assert_eq!(kind, "r");
+            assert_eq!(namespace, "pg_catalog");
+            Ok(Some(()))
+        });
+    }
+
+    extern_table!{
+        ExternalTable (
+            id: i64,
+            note: Option<String>,
+        )
+        name: "external_schema.external_table"
+        verify: true
+    }
+
+    extern_table!{
+        MisdeclaredExternalTable (
+            id: i32,
+            note: String,
+            missing: bool,
+        )
+        name: "external_schema.external_table"
+    }
+
+    #[pg_test]
+    fn test_extern_table() {
+        use crate::framework::schema::{check_extern_table, SchemaMismatch};
+
+        Spi::run("CREATE SCHEMA external_schema");
+        Spi::run("CREATE TABLE external_schema.external_table (
+            extra integer,
+            note text,
+            id bigint NOT NULL
+        )");
+        Spi::run("INSERT INTO external_schema.external_table VALUES (0, 'a', 1)");
+
+        Spi::connect(|client| {
+            let rows: Vec<(i64, Option<String>)> = query!(client
+                from: ExternalTable
+                select: (id, note)
+            ).unwrap().collect();
+            assert_eq!(rows, vec![(1, Some("a".to_string()))]);
+
+            let diff = check_extern_table::<MisdeclaredExternalTable>(&client).unwrap_err();
+            assert_eq!(diff.mismatches, vec![
+                SchemaMismatch::WrongType {
+                    column: "id",
+                    expected: "integer",
+                    actual: "bigint".to_string(),
+                },
+                SchemaMismatch::Nullable { column: "note" },
+                SchemaMismatch::MissingColumn { column: "missing" },
+            ]);
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        VerifiedTable (
+            id: i64,
+            note: Option<String>,
+        )
+        verify: true
+    }
+
+    #[pg_test]
+    fn test_verify_schema() {
+        use crate::framework::schema::SchemaMismatch;
+
+        Spi::connect(|client| {
+            let rows: Vec<(i64, Option<String>)> = query!(client
+                from: VerifiedTable
+                select: (id, note)
+            ).unwrap().collect();
+            assert_eq!(rows, vec![]);
+            assert!(VerifiedTable::verify_schema(&client).is_ok());
+            Ok(Some(()))
+        });
+
+        Spi::run("ALTER TABLE VerifiedTable ALTER COLUMN note SET NOT NULL");
+        Spi::run("ALTER TABLE VerifiedTable ADD COLUMN extra integer");
+        Spi::run("ALTER TABLE VerifiedTable ADD CONSTRAINT positive_id CHECK (id > 0)");
+
+        Spi::connect(|client| {
+            let diff = VerifiedTable::verify_schema(&client).unwrap_err();
+            assert_eq!(diff.mismatches, vec![
+                SchemaMismatch::NotNull { column: "note" },
+                SchemaMismatch::ExtraColumn { column: "extra".to_string() },
+                SchemaMismatch::UnexpectedConstraint {
+                    name: "positive_id".to_string(),
+                    definition: "CHECK ((id > 0))".to_string(),
+                },
+            ]);
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        VersionedTable (
+            id: i64,
+            #[changed("1.2", from = Option<i32>)]
+            count: i64,
+            #[since = "1.1"]
+            note: Option<String>,
+            #[since = "1.2"]
+            #[default = "0"]
+            priority: i32,
+            #[since = "1.3"]
+            #[default = "0"]
+            revision: i32,
+        )
+        since: "1.0"
+        key: (id, revision)
+        dropped: { legacy: Option<String> in "1.3" }
+    }
+
+    #[pg_test]
+    fn test_upgrade_script() {
+        use crate::framework::{PgTable, upgrade::{extension_upgrade_script, upgrade_script}};
+
+        let history = &[<VersionedTable as PgTable>::HISTORY];
+        assert_eq!(upgrade_script("1.0", "1.1", history), "\
+            ALTER TABLE VersionedTable ADD COLUMN IF NOT EXISTS note text;\n\
+        ");
+        assert_eq!(upgrade_script("1.1", "1.3", history), "\
+            ALTER TABLE VersionedTable ALTER COLUMN count TYPE bigint USING count::bigint;\n\
+            ALTER TABLE VersionedTable ALTER COLUMN count SET NOT NULL;\n\
+            ALTER TABLE VersionedTable ADD COLUMN IF NOT EXISTS priority integer NOT NULL DEFAULT 0;\n\
+            ALTER TABLE VersionedTable ALTER COLUMN priority DROP DEFAULT;\n\
+            ALTER TABLE VersionedTable ADD COLUMN IF NOT EXISTS revision integer NOT NULL DEFAULT 0;\n\
+            ALTER TABLE VersionedTable ALTER COLUMN revision DROP DEFAULT;\n\
+            ALTER TABLE VersionedTable ADD PRIMARY KEY (id, revision);\n\
+            ALTER TABLE VersionedTable DROP COLUMN IF EXISTS legacy;\n\
+        ");
+
+        // replay the whole history on a fresh table, the way the upgrade
+        // generator would, and check we end up with what `table!` would have
+        // created
+        Spi::run("DROP TABLE VersionedTable");
+        Spi::run(&extension_upgrade_script("0", "1.0", TEST_TABLES));
+        Spi::run("INSERT INTO VersionedTable VALUES (1, 2, 'old')");
+        Spi::run(&extension_upgrade_script("1.0", "1.3", TEST_TABLES));
+        Spi::connect(|client| {
+            assert!(VersionedTable::verify_schema(&client).is_ok());
+            let rows: Vec<(i64, i64, Option<String>, i32, i32)> = query!(client
+                from: VersionedTable
+                select: (id, count, note, priority, revision)
+            ).unwrap().collect();
+            assert_eq!(rows, vec![(1, 2, None, 0, 0)]);
+            Ok(Some(()))
+        });
+    }
+
+    // the checked-in extension script is generated by `cargo pgx schema`, and
+    // has to be regenerated whenever the `CREATE TABLE` of a table changes
+    #[pg_test]
+    fn test_generated_sql() {
+        let script = include_str!("../sql/table_builder-1.0.sql");
+        for table in crate::TABLES {
+            assert!(
+                script.contains(table.create),
+                "sql/table_builder-1.0.sql is out of date for `{}`, run `cargo pgx schema`",
+                table.name,
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_schema_lock() {
+        use std::path::Path;
+        use crate::framework::snapshot::{check, Snapshot};
+
+        let sql_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("sql");
+        let locked = Snapshot::read(&sql_dir.join("schema.lock.json")).unwrap();
+        let current = Snapshot::current(crate::extension_version(), crate::TABLES);
+        let no_script = sql_dir.join("does-not-exist.sql");
+        if let Err(errors) = check(&locked, &current, crate::TABLES, &no_script) {
+            panic!("{}\nrun `cargo run --bin schema-lock -- check` for details", errors.join("\n"))
+        }
+
+        let mut changed = current.clone();
+        changed.tables.get_mut("ExampleTable").unwrap().columns.pop();
+        assert_eq!(check(&locked, &changed, crate::TABLES, &no_script), Err(vec![
+            "table `ExampleTable` changed but the extension version is still 1.0".to_string(),
+        ]));
+
+        changed.version = "1.1".to_string();
+        assert_eq!(check(&locked, &changed, crate::TABLES, &no_script), Err(vec![
+            format!("no upgrade script from 1.0 to 1.1 at {}", no_script.display()),
+            "table `ExampleTable` changed but has no history for versions after 1.0".to_string(),
+        ]));
+
+        // a table missing from `TABLES` would be left out of the lock entirely
+        let listed: Vec<_> = crate::TABLES.iter().chain(TEST_TABLES).copied().collect();
+        Spi::connect(|client| {
+            let unlisted = framework::schema::unlisted_tables(&client, &listed);
+            assert!(unlisted.is_empty(), "tables missing from `TABLES`: {}", unlisted.join(", "));
+            let unlisted = framework::schema::unlisted_tables(&client, TEST_TABLES);
+            assert_eq!(unlisted.len(), crate::TABLES.len());
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        DumpedTable (
+            key: String,
+            value: i32,
+        )
+        insert: {
+            (0..3).map(|i| (i.to_string(), i))
+        }
+        dump: where "value >= 0"
+    }
+
+    #[pg_test]
+    fn test_dump_config() {
+        let filter = Spi::get_one::<String>("\
+            SELECT extcondition[array_position(extconfig, 'DumpedTable'::regclass)] \
+            FROM pg_extension \
+            WHERE extname = 'table_builder'"
+        ).unwrap();
+        assert!(filter.starts_with("WHERE (value >= 0) AND NOT EXISTS"));
+
+        Spi::run("INSERT INTO DumpedTable VALUES ('user', 10), ('negative', -1), ('0', 1)");
+        let schema = Spi::get_one::<String>("\
+            SELECT extnamespace::regnamespace::text \
+            FROM pg_extension \
+            WHERE extname = 'table_builder'"
+        ).unwrap();
+        // as pg_dump runs it
+        Spi::run("SET LOCAL search_path = ''");
+        Spi::connect(|client| {
+            let sql = format!("SELECT key FROM ONLY {}.DumpedTable {}", schema, filter);
+            let mut dumped: Vec<String> = client
+                .select(&sql, None, None)
+                .map(|row| row.by_ordinal(1).unwrap().value().unwrap())
+                .collect();
+            dumped.sort();
+            assert_eq!(dumped, vec!["0".to_string(), "user".to_string()]);
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        Unit (
+            code: String,
+            factor: f64,
+            label: Option<String>,
+        )
+        insert: file "seeds/units.csv"
+    }
+
+    #[pg_test]
+    fn test_seed_file() {
+        Spi::connect(|client| {
+            let mut units: Vec<(String, f64, Option<String>)> = query!(client
+                from: Unit
+                select: (code, factor, label)
+            ).unwrap().collect();
+            units.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(units, vec![
+                ("ft".to_string(), 0.3048, None),
+                ("in".to_string(), 0.0254, Some("inch, imperial".to_string())),
+                ("km".to_string(), 1000.0, Some("kilometre".to_string())),
+                ("m".to_string(), 1.0, Some("metre".to_string())),
+            ]);
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        Currency (
+            code: String,
+            name: String,
+            decimals: i16,
+        )
+        key: (code)
+        insert: file "seeds/currencies.json" on conflict do update
+    }
+
+    #[pg_test]
+    fn test_reseed() {
+        use crate::framework::PgWritableTable;
+
+        Spi::run("UPDATE Currency SET decimals = 3 WHERE code = 'JPY'");
+        Spi::run("INSERT INTO Currency VALUES ('XBT', 'Bitcoin', 8)");
+        Spi::run(<Currency as PgWritableTable>::SEED);
+
+        Spi::connect(|client| {
+            assert!(Currency::verify_schema(&client).is_ok());
+            let mut currencies: Vec<(String, i16)> = query!(client
+                from: Currency
+                select: (code, decimals)
+            ).unwrap().collect();
+            currencies.sort();
+            assert_eq!(currencies, vec![
+                ("EUR".to_string(), 2),
+                ("JPY".to_string(), 0),
+                ("USD".to_string(), 2),
+                ("XBT".to_string(), 8),
+            ]);
+            Ok(Some(()))
+        });
+    }
+
+    #[pg_test]
+    fn test_no_seed_functions() {
+        let functions = Spi::get_one::<i64>(
+            "SELECT count(*) FROM pg_proc WHERE proname LIKE '\\_\\_table\\_builder\\_%'"
+        );
+        assert_eq!(functions, Some(0));
+
+        let rows = Spi::get_one::<i64>("SELECT count(*) FROM KeyValueTable");
+        assert_eq!(rows, Some(10));
+    }
+
+    /// A table declared on a regular struct.
+    #[pg_table(
+        key: (id),
+        insert: { (0..2).map(|id| (id, None)) },
+    )]
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AttributeTable {
+        pub id: i32,
+        pub label: Option<String>,
+    }
+
+    #[pg_test]
+    fn test_pg_table() {
+        Spi::connect(|mut client| {
+            let row = AttributeTable { id: 2, label: Some("two".to_string()) };
+            query!(client
+                insert into: AttributeTable
+                value: row.clone()
+            ).unwrap();
+
+            let mut rows: Vec<AttributeTable> = query!(client
+                from: AttributeTable
+                select: (id, label)
+            ).unwrap().map(|(id, label)| AttributeTable { id, label }).collect();
+            rows.sort_by_key(|row| row.id);
+            assert_eq!(rows, vec![
+                AttributeTable { id: 0, label: None },
+                AttributeTable { id: 1, label: None },
+                row,
+            ]);
+            assert!(AttributeTable::verify_schema(&client).is_ok());
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        /// Rows with a description.
+        #[derive(Debug, Clone, PartialEq)]
+        pub DocumentedTable (
+            /// The row's id.
+            pub id: i32,
+            pub note: Option<String>,
+        )
+    }
+
+    #[pg_test]
+    fn test_table_attributes() {
+        let row = DocumentedTable { id: 1, note: None };
+        assert_eq!(format!("{:?}", row.clone()), "DocumentedTable { id: 1, note: None }");
+
+        let table_comment = Spi::get_one::<String>(
+            "SELECT obj_description('DocumentedTable'::regclass, 'pg_class')"
+        );
+        assert_eq!(table_comment, Some("Rows with a description.".to_string()));
+        let column_comment = Spi::get_one::<String>(
+            "SELECT col_description('DocumentedTable'::regclass, 1)"
+        );
+        assert_eq!(column_comment, Some("The row's id.".to_string()));
+        let no_comment = Spi::get_one::<String>(
+            "SELECT col_description('DocumentedTable'::regclass, 2)"
+        );
+        assert_eq!(no_comment, None);
+    }
+
+    table!{
+        Account (
+            id: i32,
+            balance: i64,
+        )
+        key: (id)
+    }
+
+    #[pg_test]
+    fn test_savepoint() {
+        use crate::framework::{client::Client, error::QueryError};
+
+        Spi::connect(|mut client| {
+            query!(client
+                insert into: Account
+                value: Account { id: 1, balance: 10 }
+            ).unwrap();
+
+            // only the savepoint is rolled back
+            let duplicate: Result<(), QueryError> = client.savepoint(|client| {
+                query!(client
+                    insert into: Account
+                    value: Account { id: 2, balance: 20 }
+                )?;
+                query!(client
+                    insert into: Account
+                    value: Account { id: 1, balance: 30 }
+                )?;
+                Ok(())
+            });
+            assert!(matches!(duplicate, Err(QueryError::UniqueViolation(_))));
+
+            let inserted: Result<(), QueryError> = client.savepoint(|client| {
+                query!(client
+                    insert into: Account
+                    value: Account { id: 3, balance: 30 }
+                )?;
+                Ok(())
+            });
+            assert!(inserted.is_ok());
+
+            let mut accounts: Vec<(i32, i64)> = query!(client
+                from: Account
+                select: (id, balance)
+            ).unwrap().collect();
+            accounts.sort();
+            assert_eq!(accounts, vec![(1, 10), (3, 30)]);
+            Ok(Some(()))
+        });
+    }
+
+    table!{
+        DriftingTable (
+            id: i32,
+            created: Option<i64>,
+        )
+        key: (id)
+    }
+
+    #[pg_test]
+    fn test_query_errors() {
+        use crate::framework::error::{sqlstate, QueryError};
+
+        Spi::connect(|mut client| {
+            query!(client
+                insert into: DriftingTable
+                values: (0..3).map(|id| DriftingTable { id, created: None })
+            ).unwrap();
+            let error = query!(client
+                insert into: DriftingTable
+                value: DriftingTable { id: 1, created: None }
+            ).unwrap_err();
+            assert!(matches!(error, QueryError::UniqueViolation(_)));
+            assert_eq!(error.code(), sqlstate::UNIQUE_VIOLATION);
+
+            // the table changes out from under its declaration; inserts would
+            // now fail too, as their parameters are typed as bigint
+            client.update("ALTER TABLE DriftingTable ALTER COLUMN created TYPE date USING NULL", None, None);
+            let error = query!(client
+                from: DriftingTable
+                select: (id, created)
+            ).map(|rows| rows.count()).unwrap_err();
+            assert!(matches!(error, QueryError::CannotCoerce(_)));
+            assert_eq!(error.message(), "cannot cast type date to bigint");
+
+            // the transaction carries on
+            let ids: Vec<i32> = query!(client
+                from: DriftingTable
+                select: (id)
+            ).unwrap().collect();
+            assert_eq!(ids.len(), 3);
+            Ok(Some(()))
+        });
+    }
 }
 
 #[cfg(test)]
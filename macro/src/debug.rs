@@ -0,0 +1,71 @@
+
+// The macros are silent by default. To see what they generate, build with the
+// `print-generated` feature to print every expansion, or set
+// `TABLE_BUILDER_DEBUG` to a directory to have each one written there as a
+// pretty-printed file, like the hand-written `extension/src/out.rs`.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+use proc_macro2::TokenStream as TokenStream2;
+
+use quote::quote;
+
+const DEBUG_DIR_VAR: &str = "TABLE_BUILDER_DEBUG";
+
+// tables are written to `<Table>.rs`
+pub fn table(name: &syn::Ident, expanded: &TokenStream2) {
+    emit(&name.to_string(), expanded)
+}
+
+// a table has any number of queries, so they're told apart by a hash of their
+// input: `query_<Table>_<hash>.rs`
+pub fn query(table: &syn::Ident, input: &TokenStream2, expanded: &TokenStream2) {
+    let mut hasher = DefaultHasher::new();
+    input.to_string().hash(&mut hasher);
+    emit(&format!("query_{}_{:016x}", table, hasher.finish()), expanded)
+}
+
+fn emit(name: &str, expanded: &TokenStream2) {
+    let print = cfg!(feature = "print-generated");
+    let dir = std::env::var_os(DEBUG_DIR_VAR).filter(|dir| !dir.is_empty());
+    if !print && dir.is_none() {
+        return
+    }
+
+    let pretty = pretty(expanded);
+    if print {
+        println!("// {}\n{}", name, pretty);
+    }
+    if let Some(dir) = dir {
+        let dir = PathBuf::from(dir);
+        let path = dir.join(format!("{}.rs", name));
+        // this is only a debugging aid, so failing to write it shouldn't fail
+        // the build
+        let written = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(&path, pretty));
+        if let Err(error) = written {
+            eprintln!("{}: cannot write `{}`: {}", DEBUG_DIR_VAR, path.display(), error);
+        }
+    }
+}
+
+// tables expand to items, queries to an expression, which gets wrapped in a
+// function to be printable. Anything else is printed as-is
+pub(crate) fn pretty(expanded: &TokenStream2) -> String {
+    if let Ok(file) = syn::parse2::<syn::File>(expanded.clone()) {
+        return prettyplease::unparse(&file)
+    }
+    let wrapped = quote! {
+        fn __query() {
+            #expanded
+        }
+    };
+    match syn::parse2::<syn::File>(wrapped) {
+        Ok(file) => prettyplease::unparse(&file),
+        Err(_) => expanded.to_string(),
+    }
+}
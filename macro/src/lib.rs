@@ -8,26 +8,49 @@ use syn::{parse::{Parse, ParseStream}, parse_macro_input};
 pub fn table(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree
     let input = parse_macro_input!(input as table_builder::Table);
+    let name = input.name.clone();
     let expanded = table_builder::expand(input);
-    // if cfg!(feature = "print-generated") {
-        println!("{}", expanded.to_string());
-    // }
+    debug::table(&name, &expanded);
+    expanded.into()
+}
+
+#[proc_macro_attribute]
+pub fn pg_table(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as syn::ItemStruct);
+    let input = match table_builder::Table::from_struct(args.into(), item) {
+        Ok(table) => table,
+        Err(error) => return error.to_compile_error().into(),
+    };
+    let name = input.name.clone();
+    let expanded = table_builder::expand(input);
+    debug::table(&name, &expanded);
+    expanded.into()
+}
+
+#[proc_macro]
+pub fn extern_table(input: TokenStream) -> TokenStream {
+    // Parse the input tokens into a syntax tree
+    let input = parse_macro_input!(input as table_builder::Table);
+    let name = input.name.clone();
+    let expanded = table_builder::expand_extern(input);
+    debug::table(&name, &expanded);
     expanded.into()
 }
 
 #[proc_macro]
 pub fn query(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree
+    let tokens = proc_macro2::TokenStream::from(input.clone());
     let input = parse_macro_input!(input as query_builder::Query);
     let expanded = input.expand();
-    // if cfg!(feature = "print-generated") {
-        println!("{}", expanded.to_string());
-    // }
+    debug::query(input.table(), &tokens, &expanded);
     expanded.into()
 }
 
 mod table_builder;
 mod query_builder;
+mod seed_file;
+mod debug;
 
 
 // utilities shared across both modules
@@ -41,6 +64,9 @@ fn optional_name(name: &syn::Ident) -> syn::Ident {
 }
 
 fn validate_marker(input: ParseStream, expected: &str) -> syn::Result<()> {
+    if input.is_empty() {
+        return Err(input.error(format!("missing `{}`", expected)))
+    }
     let field: syn::Ident = input.parse()?;
     if field != expected {
         return Err(syn::Error::new(
@@ -52,6 +78,22 @@ fn validate_marker(input: ParseStream, expected: &str) -> syn::Result<()> {
     return Ok(())
 }
 
+// for SQL keywords that aren't rust keywords, and so have no `syn::Token!`
+fn peek_word(input: ParseStream, word: &str) -> bool {
+    matches!(input.cursor().ident(), Some((ident, _)) if ident == word)
+}
+
+fn parse_word(input: ParseStream, word: &str) -> syn::Result<syn::Ident> {
+    let ident: syn::Ident = input.parse()?;
+    if ident != word {
+        return Err(syn::Error::new(
+            ident.span(),
+            format!("expected `{}` found `{}`", word, ident)
+        ))
+    }
+    Ok(ident)
+}
+
 fn parse_marked<const N: usize>(
     input: ParseStream,
     mut parsers: [(&str, &mut dyn FnMut(ParseStream) -> syn::Result<()>); N],
@@ -62,7 +104,9 @@ fn parse_marked<const N: usize>(
         |marker: &syn::Ident, parsers: &[(&str, _); N]| {
             let mut msg = format!("found `{}` expected one of", marker);
             for (i, mark) in parsers.iter().map(|&(s, _)| s).enumerate() {
-                if i + 1 == N {
+                if i == 0 {
+                    let _ = write!(&mut msg, " `{}`", mark);
+                } else if i + 1 == N {
                     let _ = write!(&mut msg, ", or `{}`", mark);
                 } else {
                     let _ = write!(&mut msg, ", `{}`", mark);
@@ -71,12 +115,24 @@ fn parse_marked<const N: usize>(
             Err(syn::Error::new(marker.span(),msg,))
         };
 
+    let mut seen = [false; N];
     'parse_marked: while !input.is_empty() {
         let marker: syn::Ident = input.parse()?;
         let _: syn::Token![:] = input.parse()?;
-        for (mark, parser) in &mut parsers {
+        for (i, (mark, parser)) in parsers.iter_mut().enumerate() {
             if marker == mark {
+                if seen[i] {
+                    return Err(syn::Error::new(
+                        marker.span(),
+                        format!("duplicate `{}`", marker),
+                    ))
+                }
+                seen[i] = true;
                 parser(input)?;
+                // markers may be comma separated, as is usual in attributes
+                if input.peek(syn::Token![,]) {
+                    let _: syn::Token![,] = input.parse()?;
+                }
                 continue 'parse_marked
             }
         }
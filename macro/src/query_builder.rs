@@ -5,6 +5,8 @@ use quote::quote;
 
 use syn::{parse::{Parse, ParseStream}, punctuated::Punctuated};
 
+// each is parsed once per macro call, so the size of the variants doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum Query {
     Select(Select),
     Insert(Insert),
@@ -21,11 +23,15 @@ impl Parse for Query {
         } else if first_marker == "insert" {
             let i = Insert::parse_after_insert(input, spi_client)?;
             Ok(Self::Insert(i))
+        } else if first_marker == "exists" {
+            let _: syn::Token![:] = input.parse()?;
+            let s = Select::parse_exists(input, spi_client)?;
+            Ok(Self::Select(s))
         } else {
             Err(syn::Error::new(
                 first_marker.span(),
                 format!(
-                    "expected one of `from` or `insert` found `{}`",
+                    "expected one of `from`, `insert`, or `exists` found `{}`",
                     first_marker,
                 )
             ))
@@ -40,13 +46,67 @@ impl Query {
             Query::Insert(i) => i.expand(),
         }
     }
+
+    // the table the query is from, or inserts into
+    pub(crate) fn table(&self) -> &syn::Ident {
+        match self {
+            Query::Select(s) => &s.from.table,
+            Query::Insert(i) => &i.table,
+        }
+    }
 }
 
 pub struct Select {
     spi_client: syn::Ident,
-    table: syn::Ident,
-    fields: Punctuated<syn::Ident, syn::Token![,]>,
+    from: From,
+    kind: SelectKind,
+    fields: Punctuated<SelectItem, syn::Token![,]>,
     where_clause: Option<syn::LitStr>,
+    distinct: Option<Distinct>,
+    group_by: Option<Punctuated<ColumnRef, syn::Token![,]>>,
+    order_by: Option<Punctuated<OrderBy, syn::Token![,]>>,
+    limit: Option<syn::Expr>,
+    lock: Option<String>,
+    fetch_size: Option<syn::Expr>,
+}
+
+// what a select returns
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SelectKind {
+    // `select:`, an iterator over the rows
+    Rows,
+    // `select_one:`, the only row
+    One,
+    // `select_optional:`, the row if there is one
+    Optional,
+    // `exists:`, whether there are any rows
+    Exists,
+}
+
+impl Parse for SelectKind {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Err(input.error("missing `select`"))
+        }
+        let marker: syn::Ident = input.parse()?;
+        let kind = if marker == "select" {
+            SelectKind::Rows
+        } else if marker == "select_one" {
+            SelectKind::One
+        } else if marker == "select_optional" {
+            SelectKind::Optional
+        } else {
+            return Err(syn::Error::new(
+                marker.span(),
+                format!(
+                    "expected one of `select`, `select_one`, or `select_optional` found `{}`",
+                    marker,
+                ),
+            ))
+        };
+        let _: syn::Token![:] = input.parse()?;
+        Ok(kind)
+    }
 }
 
 impl Parse for Select {
@@ -62,75 +122,916 @@ impl Parse for Select {
 impl Select {
     pub(crate) fn parse_after_first_marker(input: ParseStream, spi_client: syn::Ident)
     -> syn::Result<Self> {
-        let table = input.parse()?;
+        let from: From = input.parse()?;
 
-        super::validate_marker(input, "select")?;
+        let kind = input.parse()?;
         let content;
         let _ = syn::parenthesized!(content in input);
         let fields = Punctuated::parse_terminated(&content)?;
 
+        Self::parse_clauses(input, spi_client, from, kind, fields)
+    }
+
+    // `exists: Table where: "..."`, which selects no columns
+    pub(crate) fn parse_exists(input: ParseStream, spi_client: syn::Ident) -> syn::Result<Self> {
+        let from = input.parse()?;
+        Self::parse_clauses(input, spi_client, from, SelectKind::Exists, Punctuated::new())
+    }
+
+    fn parse_clauses(
+        input: ParseStream,
+        spi_client: syn::Ident,
+        from: From,
+        kind: SelectKind,
+        fields: Punctuated<SelectItem, syn::Token![,]>,
+    ) -> syn::Result<Self> {
         let mut where_clause = None;
-        if input.peek(syn::Token![where]) {
-            let _: syn::Token![where] = input.parse()?;
+        let mut distinct = None;
+        let mut group_by = None;
+        let mut order_by: Option<Punctuated<OrderBy, _>> = None;
+        let mut limit = None;
+        let mut lock = None;
+        let mut fetch_size = None;
+        while !input.is_empty() {
+            if input.peek(syn::Token![where]) {
+                let where_token: syn::Token![where] = input.parse()?;
+                let _: syn::Token![:] = input.parse()?;
+                if where_clause.is_some() {
+                    return Err(syn::Error::new(where_token.span, "duplicate `where`"))
+                }
+                where_clause = Some(input.parse()?);
+                continue
+            }
+
+            let marker: syn::Ident = input.parse()?;
+            if kind == SelectKind::Exists {
+                return Err(syn::Error::new(
+                    marker.span(),
+                    format!("expected `where` found `{}`", marker),
+                ))
+            }
+            // `distinct` on its own takes no value
+            if marker == "distinct" {
+                if distinct.is_some() {
+                    return Err(syn::Error::new(marker.span(), "duplicate `distinct`"))
+                }
+                distinct = Some(Distinct::parse_after_keyword(input, marker)?);
+                continue
+            }
+            if marker == "group" || marker == "order" {
+                super::parse_word(input, "by")?;
+            }
             let _: syn::Token![:] = input.parse()?;
-            where_clause = Some(input.parse()?);
+            let duplicate = || Err(syn::Error::new(
+                marker.span(),
+                format!("duplicate `{}`", marker),
+            ));
+            if marker == "group" {
+                if group_by.is_some() {
+                    return duplicate()
+                }
+                let content;
+                let _ = syn::parenthesized!(content in input);
+                group_by = Some(Punctuated::parse_terminated(&content)?);
+            } else if marker == "order" {
+                if order_by.is_some() {
+                    return duplicate()
+                }
+                let content;
+                let _ = syn::parenthesized!(content in input);
+                order_by = Some(Punctuated::parse_terminated(&content)?);
+            } else if marker == "limit" {
+                if limit.is_some() {
+                    return duplicate()
+                }
+                limit = Some(input.parse()?);
+            } else if marker == "lock" {
+                if lock.is_some() {
+                    return duplicate()
+                }
+                lock = Some(parse_lock(input)?);
+            } else if marker == "fetch_size" {
+                if fetch_size.is_some() {
+                    return duplicate()
+                }
+                fetch_size = Some(input.parse()?);
+            } else {
+                return Err(syn::Error::new(
+                    marker.span(),
+                    format!(
+                        "expected one of `where`, `distinct`, `group by`, `order by`, `limit`, `lock`, or `fetch_size` found `{}`",
+                        marker,
+                    ),
+                ))
+            }
+        }
+
+        for field in &fields {
+            if let Some(column) = field.column() {
+                from.resolve(column)?;
+            }
+        }
+        let distinct_on = distinct.iter().flat_map(|distinct| distinct.on.iter().flatten());
+        let ordered = order_by.iter().flatten().map(|order| &order.column);
+        for column in group_by.iter().flatten().chain(distinct_on).chain(ordered) {
+            from.resolve(column)?;
+        }
+        validate_grouping(&from, &fields, group_by.as_ref())?;
+        validate_ordering(&from, &fields, group_by.as_ref(), distinct.as_ref(), order_by.as_ref())?;
+        if let (Some(distinct), Some(_)) = (&distinct, &lock) {
+            return Err(syn::Error::new(
+                distinct.keyword.span(),
+                "`lock` can't be used with `distinct`",
+            ))
         }
 
         Ok(Self {
             spi_client,
-            table,
+            from,
+            kind,
             fields,
             where_clause,
+            distinct,
+            group_by,
+            order_by,
+            limit,
+            lock,
+            fetch_size,
         })
     }
 
     pub(crate) fn expand(&self) -> TokenStream2 {
         use std::fmt::Write as _;
 
-        let Select { spi_client, table, fields, where_clause } = self;
-        let mod_name = super::table_mod(&table);
+        let Select {
+            spi_client,
+            from,
+            kind,
+            fields,
+            where_clause,
+            distinct,
+            group_by,
+            order_by,
+            limit,
+            lock,
+            fetch_size,
+        } = self;
+        // every column was resolved while parsing
+        let resolve = |column| from.resolve(column).unwrap();
 
         let mut query_string = "SELECT ".to_string();
-        let mut first = true;
-        for field in fields {
-            if !first {
+        if let Some(distinct) = distinct {
+            query_string.push_str("DISTINCT ");
+            if let Some(on) = &distinct.on {
+                let on: Vec<_> = on.iter().map(|column| resolve(column).sql).collect();
+                let _ = write!(&mut query_string, "ON ({}) ", on.join(", "));
+            }
+        }
+        if *kind == SelectKind::Exists {
+            query_string.push('1');
+        }
+        for (i, field) in fields.iter().enumerate() {
+            if i != 0 {
                 query_string.push_str(", ")
             }
-            first = false;
             // cast each column to the SQL type we expect so that any errors in
             // DDL will just cause SQL errors not corruption. It might be nicer
             // to do this with a compile-time concatenation
-            let _ = write!(&mut query_string, "{field}::{{{field}}}", field=field);
+            let column = field.column().map(resolve);
+            let _ = write!(&mut query_string, "{}::{{__column_{}}}", field.sql(column.as_ref()), i);
         }
-        let _ = write!(&mut query_string, " FROM {}", table);
+        let _ = write!(&mut query_string, " FROM {}", from.sql());
         if let Some(where_clause) = where_clause {
             let _ = write!(&mut query_string, " WHERE {}", where_clause.value());
         }
+        if let Some(group_by) = group_by {
+            query_string.push_str(" GROUP BY ");
+            for (i, column) in group_by.iter().enumerate() {
+                if i != 0 {
+                    query_string.push_str(", ")
+                }
+                query_string.push_str(&resolve(column).sql);
+            }
+        }
+        if let Some(order_by) = order_by {
+            query_string.push_str(" ORDER BY ");
+            for (i, order) in order_by.iter().enumerate() {
+                if i != 0 {
+                    query_string.push_str(", ")
+                }
+                query_string.push_str(&resolve(&order.column).sql);
+                if order.descending {
+                    query_string.push_str(" DESC");
+                }
+            }
+        }
+        if limit.is_some() {
+            query_string.push_str(" LIMIT {__limit}");
+        } else if *kind == SelectKind::Exists {
+            query_string.push_str(" LIMIT 1");
+        } else if matches!(kind, SelectKind::One | SelectKind::Optional) {
+            // a second row is enough to know there's more than one
+            query_string.push_str(" LIMIT 2");
+        }
+        if let Some(lock) = lock {
+            let _ = write!(&mut query_string, " {}", lock);
+        }
 
-        let column_types = fields.iter().map(|field| quote!{
-            #field = <#mod_name::#field as framework::PgTyped>::SQL_TYPE
+        let column_types = fields.iter().enumerate().map(|(i, field)| {
+            let arg = column_var(i);
+            let sql_type = field.sql_type(field.column().map(resolve).as_ref());
+            quote!{ #arg = #sql_type }
         });
+        let limit_var = limit.iter().map(|limit| quote!{ let __limit: i64 = #limit; });
+        let limit_arg = limit.iter().map(|_| quote!{ __limit = __limit });
+        let limit = match limit {
+            Some(_) => quote!{ Some(__limit) },
+            None if *kind == SelectKind::Exists => quote!{ Some(1) },
+            None if matches!(kind, SelectKind::One | SelectKind::Optional) => quote!{ Some(2) },
+            None => quote!{ None },
+        };
+        let fetch_size = match fetch_size {
+            Some(fetch_size) => quote!{ Some(#fetch_size) },
+            None => quote!{ None },
+        };
+        let filter = match where_clause {
+            Some(where_clause) => quote!{ Some(#where_clause) },
+            None => quote!{ None },
+        };
+        let selected = fields.iter()
+            .map(|field| field.client_ref(field.column().map(resolve).as_ref()));
+        let grouped = group_by.iter().flatten().map(|column| resolve(column).client_ref());
+        let distinct = match distinct {
+            None => quote!{ framework::client::Distinct::All },
+            Some(Distinct { on: None, .. }) => quote!{ framework::client::Distinct::Rows },
+            Some(Distinct { on: Some(on), .. }) => {
+                let on = on.iter().map(|column| resolve(column).client_ref());
+                quote!{ framework::client::Distinct::On(&[#(#on),*]) }
+            },
+        };
+        let ordered = order_by.iter().flatten().map(|order| {
+            let column = resolve(&order.column).client_ref();
+            let descending = order.descending;
+            quote!{ framework::client::OrderBy { column: #column, descending: #descending } }
+        });
+        let table_refs = from.table_refs();
+        let join_refs = from.join_refs();
+        // columns that are only used for grouping, counting, deduplicating or
+        // sorting aren't read back, make sure they exist anyway
+        let counted = fields.iter().filter_map(|field| match field {
+            SelectItem::Aggregate { function: Aggregate::Count, column } => column.as_ref(),
+            _ => None,
+        });
+        let distinct_on = self.distinct.iter().flat_map(|distinct| distinct.on.iter().flatten());
+        let sorted = order_by.iter().flatten().map(|order| &order.column);
+        let unread = group_by.iter().flatten().chain(counted).chain(distinct_on).chain(sorted);
+        let column_checks = unread.map(|column| {
+            let ty = resolve(column).ty();
+            quote!{ let _: Option<#ty> = None; }
+        });
+        let join_checks = from.join_checks();
+        let table_names = from.table_names();
+        let tables = from.tables();
 
-
-        let mut field_idx = 0usize;
-        let field_reads = fields.iter().map(|field| {
-            field_idx += 1;
-            let optional_name = super::optional_name(field);
+        let field_reads = fields.iter().enumerate().map(|(i, field)| {
+            let var = column_var(i);
+            let idx = i + 1;
+            let (read_ty, ty) = field.rust_types(field.column().map(resolve).as_ref());
             quote! {
-                let #field: #mod_name::#optional_name = __tuple.by_ordinal(#field_idx).unwrap().value();
-                let #field: #mod_name::#field = <_ as framework::UnwrapTo<_>>::unwrap_to(#field);
+                let #var: #read_ty = framework::client::Row::get(&__tuple, #idx);
+                let #var: #ty = <_ as framework::UnwrapTo<_>>::unwrap_to(#var);
             }
         });
 
-        let field_names = fields.iter();
+        let field_names = (0..fields.len()).map(column_var);
+
+        // row-locking clauses make the query read-write as far as the SPI is
+        // concerned, unless it's read through a cursor
+        let read_only = lock.is_none();
+        let select_rows = if read_only || self.fetch_size.is_some() {
+            quote!{ select_rows }
+        } else {
+            quote!{ select_rows_mut }
+        };
+
+        let rows = match kind {
+            SelectKind::Rows => quote!{},
+            SelectKind::One => quote!{ .and_then(framework::client::one_row) },
+            SelectKind::Optional => quote!{ .and_then(framework::client::optional_row) },
+            SelectKind::Exists => quote!{ .map(|mut __rows| __rows.next().is_some()) },
+        };
 
         quote! {
-            #spi_client.select(&format!(#query_string, #(#column_types,)*), None, None).map(|__tuple| {
-                #(#field_reads)*
-                (#(#field_names),*)
+            {
+                use framework::client::Client as _;
+                #(#column_checks)*
+                #(#join_checks)*
+                #(#limit_var)*
+                let __sql = format!(#query_string, #(#column_types,)* #(#table_names,)* #(#limit_arg,)*);
+                let __query = framework::client::Select {
+                    sql: &__sql,
+                    tables: &[#(#table_refs),*],
+                    joins: &[#(#join_refs),*],
+                    columns: &[#(#selected),*],
+                    filter: #filter,
+                    distinct: #distinct,
+                    group_by: &[#(#grouped),*],
+                    order_by: &[#(#ordered),*],
+                    limit: #limit,
+                    fetch_size: #fetch_size,
+                    read_only: #read_only,
+                };
+                #(#spi_client.before_query::<#tables>();)*
+                #spi_client.#select_rows(&__query).map(|__rows| __rows.map(|__tuple| {
+                    #(#field_reads)*
+                    (#(#field_names),*)
+                }))#rows
+            }
+        }
+    }
+}
+
+fn column_var(i: usize) -> syn::Ident {
+    quote::format_ident!("__column_{}", i)
+}
+
+// `distinct`, or `distinct on: (columns)`
+struct Distinct {
+    keyword: syn::Ident,
+    on: Option<Punctuated<ColumnRef, syn::Token![,]>>,
+}
+
+impl Distinct {
+    fn parse_after_keyword(input: ParseStream, keyword: syn::Ident) -> syn::Result<Self> {
+        use super::{parse_word, peek_word};
+
+        if !peek_word(input, "on") {
+            return Ok(Self { keyword, on: None })
+        }
+        parse_word(input, "on")?;
+        let _: syn::Token![:] = input.parse()?;
+        let content;
+        let _ = syn::parenthesized!(content in input);
+        let on = Punctuated::parse_terminated(&content)?;
+        Ok(Self { keyword, on: Some(on) })
+    }
+}
+
+// `column`, `column asc` or `column desc`
+struct OrderBy {
+    column: ColumnRef,
+    descending: bool,
+}
+
+impl Parse for OrderBy {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        use super::{parse_word, peek_word};
+
+        let column = input.parse()?;
+        let descending = peek_word(input, "desc");
+        if descending {
+            parse_word(input, "desc")?;
+        } else if peek_word(input, "asc") {
+            parse_word(input, "asc")?;
+        }
+        Ok(Self { column, descending })
+    }
+}
+
+// the tables a select reads from, `A join B on A.x = B.y left join ...`
+struct From {
+    table: syn::Ident,
+    joins: Vec<Join>,
+}
+
+struct Join {
+    kind: JoinKind,
+    table: syn::Ident,
+    left: ColumnRef,
+    right: ColumnRef,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum JoinKind {
+    Inner,
+    Left,
+}
+
+// a possibly table-qualified column, `column` or `Table.column`
+struct ColumnRef {
+    table: Option<syn::Ident>,
+    column: syn::Ident,
+}
+
+// a column along with what we know about it from the `from` clause
+struct ResolvedColumn<'c> {
+    mod_name: syn::Ident,
+    // index of the table in the `from` clause
+    table: usize,
+    column: &'c syn::Ident,
+    // columns from the right side of a left join may be NULL regardless of
+    // their declared type
+    nullable: bool,
+    sql: String,
+}
+
+impl Parse for From {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        use super::{parse_word, peek_word};
+
+        let table: syn::Ident = input.parse()?;
+        let mut from = Self { table, joins: vec![] };
+        loop {
+            let kind = if peek_word(input, "join") {
+                JoinKind::Inner
+            } else if peek_word(input, "inner") {
+                parse_word(input, "inner")?;
+                JoinKind::Inner
+            } else if peek_word(input, "left") {
+                parse_word(input, "left")?;
+                JoinKind::Left
+            } else {
+                break
+            };
+            parse_word(input, "join")?;
+            let table: syn::Ident = input.parse()?;
+            if from.tables().any(|t| *t == table) {
+                return Err(syn::Error::new(
+                    table.span(),
+                    format!("table `{}` appears more than once in `from`", table),
+                ))
+            }
+            parse_word(input, "on")?;
+            let left = input.parse()?;
+            let _: syn::Token![=] = input.parse()?;
+            let right = input.parse()?;
+            from.joins.push(Join { kind, table, left, right });
+
+            let join = from.joins.last().unwrap();
+            from.resolve(&join.left)?;
+            from.resolve(&join.right)?;
+        }
+        Ok(from)
+    }
+}
+
+impl Parse for ColumnRef {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let first: syn::Ident = input.parse()?;
+        if !input.peek(syn::Token![.]) {
+            return Ok(Self { table: None, column: first })
+        }
+        let _: syn::Token![.] = input.parse()?;
+        let column = input.parse()?;
+        Ok(Self { table: Some(first), column })
+    }
+}
+
+impl ColumnRef {
+    fn span(&self) -> proc_macro2::Span {
+        self.table.as_ref().unwrap_or(&self.column).span()
+    }
+}
+
+impl From {
+    fn tables(&self) -> impl Iterator<Item = &syn::Ident> {
+        std::iter::once(&self.table).chain(self.joins.iter().map(|join| &join.table))
+    }
+
+    fn resolve<'c>(&self, column: &'c ColumnRef) -> syn::Result<ResolvedColumn<'c>> {
+        let ColumnRef { table, column } = column;
+        let table = match table {
+            Some(table) => table,
+            None if self.joins.is_empty() => {
+                return Ok(ResolvedColumn {
+                    mod_name: super::table_mod(&self.table),
+                    table: 0,
+                    column,
+                    nullable: false,
+                    sql: column.to_string(),
+                })
+            },
+            None => return Err(syn::Error::new(
+                column.span(),
+                format!(
+                    "column `{}` must be qualified with its table when selecting from a join",
+                    column,
+                ),
+            )),
+        };
+
+        if *table == self.table {
+            return Ok(ResolvedColumn {
+                mod_name: super::table_mod(table),
+                table: 0,
+                column,
+                nullable: false,
+                sql: format!("{}.{}", table, column),
             })
         }
+        match self.joins.iter().position(|join| join.table == *table) {
+            Some(i) => Ok(ResolvedColumn {
+                mod_name: super::table_mod(table),
+                table: i + 1,
+                column,
+                nullable: self.joins[i].kind == JoinKind::Left,
+                sql: format!("{}.{}", table, column),
+            }),
+            None => Err(syn::Error::new(
+                table.span(),
+                format!("table `{}` is not part of the `from` clause", table),
+            )),
+        }
+    }
+
+    // the tables' SQL names are filled in from `PgTable::NAME` at runtime, each
+    // table is aliased to its rust name so columns can be qualified by it
+    fn sql(&self) -> String {
+        use std::fmt::Write as _;
+
+        let mut sql = format!("{{__table_0}} {}", self.table);
+        for (i, Join { kind, table, left, right }) in self.joins.iter().enumerate() {
+            let kind = match kind {
+                JoinKind::Inner => "JOIN",
+                JoinKind::Left => "LEFT JOIN",
+            };
+            let left = self.resolve(left).unwrap();
+            let right = self.resolve(right).unwrap();
+            let _ = write!(
+                &mut sql,
+                " {} {{__table_{}}} {} ON {} = {}",
+                kind, i + 1, table, left.sql, right.sql,
+            );
+        }
+        sql
+    }
+
+    fn table_names(&self) -> impl Iterator<Item = TokenStream2> + '_ {
+        self.tables().enumerate().map(|(i, table)| {
+            let arg = quote::format_ident!("__table_{}", i);
+            quote!{ #arg = <#table as framework::PgTable>::NAME }
+        })
+    }
+
+    // the tables and joins as `framework::client` describes them
+    fn table_refs(&self) -> impl Iterator<Item = TokenStream2> + '_ {
+        self.tables().map(|table| {
+            let alias = table.to_string();
+            quote! {
+                framework::client::TableRef {
+                    alias: #alias,
+                    name: <#table as framework::PgTable>::NAME,
+                    columns: <#table as framework::PgTable>::COLUMNS,
+                }
+            }
+        })
+    }
+
+    fn join_refs(&self) -> impl Iterator<Item = TokenStream2> + '_ {
+        self.joins.iter().enumerate().map(move |(i, Join { kind, left, right, .. })| {
+            let table = i + 1;
+            let left_join = *kind == JoinKind::Left;
+            let left = self.resolve(left).unwrap().client_ref();
+            let right = self.resolve(right).unwrap().client_ref();
+            quote! {
+                framework::client::Join { table: #table, left: #left_join, on: (#left, #right) }
+            }
+        })
+    }
+
+    // joined columns must have the same type, modulo nullability
+    fn join_checks(&self) -> impl Iterator<Item = TokenStream2> + '_ {
+        self.joins.iter().map(move |Join { left, right, .. }| {
+            let left = self.resolve(left).unwrap().optional_ty();
+            let right_span = right.span();
+            let right = self.resolve(right).unwrap().optional_ty();
+            quote::quote_spanned! {right_span=>
+                let _ = |__left: #left| -> #right { __left };
+            }
+        })
+    }
+}
+
+impl<'c> ResolvedColumn<'c> {
+    fn client_ref(&self) -> TokenStream2 {
+        let table = self.table;
+        let column = self.column.to_string();
+        quote!{ framework::client::ColumnRef { table: #table, column: #column } }
+    }
+
+    // the column's declared type
+    fn ty(&self) -> TokenStream2 {
+        let ResolvedColumn { mod_name, column, .. } = self;
+        quote::quote_spanned!{column.span()=> #mod_name::__column!(#column) }
+    }
+
+    fn optional_ty(&self) -> TokenStream2 {
+        let ResolvedColumn { mod_name, column, .. } = self;
+        quote::quote_spanned!{column.span()=> #mod_name::__column!(optional #column) }
+    }
+
+    // the type the column is returned as
+    fn result_ty(&self) -> TokenStream2 {
+        if self.nullable {
+            self.optional_ty()
+        } else {
+            self.ty()
+        }
+    }
+}
+
+// an entry in a `select:` list, either a plain column or an aggregate over one
+enum SelectItem {
+    Column(ColumnRef),
+    Aggregate {
+        function: Aggregate,
+        // `None` for `count(*)`
+        column: Option<ColumnRef>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Count,
+    Min,
+    Max,
+    Sum,
+}
+
+impl Parse for SelectItem {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if !input.peek2(syn::token::Paren) {
+            return Ok(Self::Column(input.parse()?))
+        }
+
+        let name: syn::Ident = input.parse()?;
+        let function = if name == "count" {
+            Aggregate::Count
+        } else if name == "min" {
+            Aggregate::Min
+        } else if name == "max" {
+            Aggregate::Max
+        } else if name == "sum" {
+            Aggregate::Sum
+        } else {
+            return Err(syn::Error::new(
+                name.span(),
+                format!(
+                    "expected one of `count`, `min`, `max`, or `sum` found `{}`",
+                    name,
+                ),
+            ))
+        };
+
+        let content;
+        let _ = syn::parenthesized!(content in input);
+        let column = if function == Aggregate::Count && content.peek(syn::Token![*]) {
+            let _: syn::Token![*] = content.parse()?;
+            None
+        } else {
+            Some(content.parse()?)
+        };
+        if !content.is_empty() {
+            return Err(content.error("expected a single column"))
+        }
+        Ok(Self::Aggregate { function, column })
+    }
+}
+
+impl SelectItem {
+    fn column(&self) -> Option<&ColumnRef> {
+        match self {
+            SelectItem::Column(column) => Some(column),
+            SelectItem::Aggregate { column, .. } => column.as_ref(),
+        }
+    }
+
+    fn sql(&self, column: Option<&ResolvedColumn>) -> String {
+        match self {
+            SelectItem::Column(_) => column.unwrap().sql.clone(),
+            SelectItem::Aggregate { function, .. } => {
+                let function = match function {
+                    Aggregate::Count => "count",
+                    Aggregate::Min => "min",
+                    Aggregate::Max => "max",
+                    Aggregate::Sum => "sum",
+                };
+                match column {
+                    Some(column) => format!("{}({})", function, column.sql),
+                    None => format!("{}(*)", function),
+                }
+            },
+        }
+    }
+
+    fn client_ref(&self, column: Option<&ResolvedColumn>) -> TokenStream2 {
+        let column = column.map(ResolvedColumn::client_ref);
+        match self {
+            SelectItem::Column(_) => quote!{ framework::client::Selected::Column(#column) },
+            SelectItem::Aggregate { function: Aggregate::Count, column: None } => {
+                quote!{ framework::client::Selected::Count(None) }
+            },
+            SelectItem::Aggregate { function: Aggregate::Count, .. } => {
+                quote!{ framework::client::Selected::Count(Some(#column)) }
+            },
+            SelectItem::Aggregate { function: Aggregate::Min, .. } => {
+                quote!{ framework::client::Selected::Min(#column) }
+            },
+            SelectItem::Aggregate { function: Aggregate::Max, .. } => {
+                quote!{ framework::client::Selected::Max(#column) }
+            },
+            SelectItem::Aggregate { function: Aggregate::Sum, .. } => {
+                quote!{ framework::client::Selected::Sum(#column) }
+            },
+        }
+    }
+
+    // the SQL type the result is cast to
+    fn sql_type(&self, column: Option<&ResolvedColumn>) -> TokenStream2 {
+        match (self, column) {
+            (SelectItem::Column(_), Some(column))
+            | (SelectItem::Aggregate { function: Aggregate::Min | Aggregate::Max, .. }, Some(column)) => {
+                let ty = column.ty();
+                quote!{ <#ty as framework::PgTyped>::SQL_TYPE }
+            },
+            (SelectItem::Aggregate { function: Aggregate::Count, .. }, _) => quote!{
+                <i64 as framework::PgTyped>::SQL_TYPE
+            },
+            (SelectItem::Aggregate { function: Aggregate::Sum, .. }, Some(column)) => {
+                let ty = column.ty();
+                quote!{ <<#ty as framework::PgSum>::Sum as framework::PgTyped>::SQL_TYPE }
+            },
+            (_, None) => unreachable!("only count(*) has no column"),
+        }
+    }
+
+    // the type the column is read as, and the type it's returned as
+    fn rust_types(&self, column: Option<&ResolvedColumn>) -> (TokenStream2, TokenStream2) {
+        match (self, column) {
+            (SelectItem::Column(_), Some(column)) => {
+                (column.optional_ty(), column.result_ty())
+            },
+            (SelectItem::Aggregate { function: Aggregate::Count, .. }, _) => {
+                (quote!{ Option<i64> }, quote!{ i64 })
+            },
+            // aggregates other than count are NULL over zero rows
+            (SelectItem::Aggregate { function: Aggregate::Min | Aggregate::Max, .. }, Some(column)) => {
+                (column.optional_ty(), column.optional_ty())
+            },
+            (SelectItem::Aggregate { function: Aggregate::Sum, .. }, Some(column)) => {
+                let ty = column.ty();
+                let ty = quote!{ Option<<#ty as framework::PgSum>::Sum> };
+                (ty.clone(), ty)
+            },
+            (_, None) => unreachable!("only count(*) has no column"),
+        }
+    }
+}
+
+// plain columns must be grouped on once any aggregates are in play, checking
+// here gives a better error than waiting for postgres to do so at runtime
+fn validate_grouping(
+    from: &From,
+    fields: &Punctuated<SelectItem, syn::Token![,]>,
+    group_by: Option<&Punctuated<ColumnRef, syn::Token![,]>>,
+) -> syn::Result<()> {
+    let has_aggregate = fields.iter()
+        .any(|field| matches!(field, SelectItem::Aggregate { .. }));
+    if !has_aggregate && group_by.is_none() {
+        return Ok(())
+    }
+
+    for field in fields {
+        if let SelectItem::Column(column) = field {
+            let resolved = from.resolve(column)?;
+            let grouped = group_by.into_iter().flatten().any(|group| {
+                matches!(from.resolve(group), Ok(group) if group.sql == resolved.sql)
+            });
+            if !grouped {
+                return Err(syn::Error::new(
+                    column.span(),
+                    format!(
+                        "column `{}` must appear in `group by` or be used in an aggregate",
+                        resolved.sql,
+                    ),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+// postgres' rules for what a select can be sorted by, which like the grouping
+// rules are better checked here than at runtime
+fn validate_ordering(
+    from: &From,
+    fields: &Punctuated<SelectItem, syn::Token![,]>,
+    group_by: Option<&Punctuated<ColumnRef, syn::Token![,]>>,
+    distinct: Option<&Distinct>,
+    order_by: Option<&Punctuated<OrderBy, syn::Token![,]>>,
+) -> syn::Result<()> {
+    // every column was resolved while parsing
+    let sql = |column| from.resolve(column).unwrap().sql;
+    let order_by: Vec<_> = order_by.into_iter().flatten().collect();
+
+    let has_aggregate = fields.iter()
+        .any(|field| matches!(field, SelectItem::Aggregate { .. }));
+    if has_aggregate || group_by.is_some() {
+        for order in &order_by {
+            let grouped = group_by.into_iter().flatten()
+                .any(|group| sql(group) == sql(&order.column));
+            if !grouped {
+                return Err(syn::Error::new(
+                    order.column.span(),
+                    format!("column `{}` must appear in `group by` to be sorted on", sql(&order.column)),
+                ))
+            }
+        }
+    }
+
+    match distinct {
+        None => {},
+        // the rows are sorted after they're deduplicated, so can only be
+        // sorted on what was selected
+        Some(Distinct { on: None, .. }) => for order in &order_by {
+            let selected = fields.iter().any(|field| match field {
+                SelectItem::Column(column) => sql(column) == sql(&order.column),
+                _ => false,
+            });
+            if !selected {
+                return Err(syn::Error::new(
+                    order.column.span(),
+                    format!(
+                        "column `{}` must be selected to be sorted on with `distinct`",
+                        sql(&order.column),
+                    ),
+                ))
+            }
+        },
+        // the first row of each group is kept, so the groups have to be
+        // sorted on first for that to mean anything
+        Some(Distinct { keyword, on: Some(on) }) => {
+            let on: Vec<_> = on.iter().map(sql).collect();
+            let leading: Vec<_> = order_by.iter().take(on.len()).map(|order| sql(&order.column)).collect();
+            let error = || format!(
+                "`distinct on` requires an `order by` starting with its columns, `{}`",
+                on.join("`, `"),
+            );
+            if leading.len() < on.len() {
+                let span = order_by.last().map_or(keyword.span(), |order| order.column.span());
+                return Err(syn::Error::new(span, error()))
+            }
+            for (order, column) in order_by.iter().zip(&leading) {
+                if !on.contains(column) {
+                    return Err(syn::Error::new(order.column.span(), error()))
+                }
+            }
+            // e.g. `distinct on: (a, b)` with `order by: (a, a)`
+            if on.iter().any(|column| !leading.contains(column)) {
+                return Err(syn::Error::new(keyword.span(), error()))
+            }
+        },
     }
+    Ok(())
+}
+
+// parses the row-locking clause following `lock:`, e.g.
+// `for update skip locked`, into its SQL
+fn parse_lock(input: ParseStream) -> syn::Result<String> {
+    use super::{parse_word, peek_word};
+
+    let _: syn::Token![for] = input.parse()?;
+    let strength: syn::Ident = input.parse()?;
+    let mut lock = if strength == "update" {
+        "FOR UPDATE".to_string()
+    } else if strength == "share" {
+        "FOR SHARE".to_string()
+    } else if strength == "no" {
+        parse_word(input, "key")?;
+        parse_word(input, "update")?;
+        "FOR NO KEY UPDATE".to_string()
+    } else if strength == "key" {
+        parse_word(input, "share")?;
+        "FOR KEY SHARE".to_string()
+    } else {
+        return Err(syn::Error::new(
+            strength.span(),
+            format!(
+                "expected one of `update`, `no key update`, `share`, or `key share` found `{}`",
+                strength,
+            ),
+        ))
+    };
+
+    if peek_word(input, "skip") {
+        parse_word(input, "skip")?;
+        parse_word(input, "locked")?;
+        lock.push_str(" SKIP LOCKED");
+    } else if peek_word(input, "nowait") {
+        parse_word(input, "nowait")?;
+        lock.push_str(" NOWAIT");
+    }
+    Ok(lock)
 }
 
 pub struct Insert {
@@ -139,9 +1040,13 @@ pub struct Insert {
     values: Values,
 }
 
+#[allow(clippy::large_enum_variant)]
 enum Values {
     Single(syn::Expr),
-    Multiple(syn::Expr),
+    Multiple {
+        values: syn::Expr,
+        batch_size: Option<syn::Expr>,
+    },
 }
 
 impl Insert {
@@ -156,7 +1061,13 @@ impl Insert {
             if val_marker == "value" {
                 Values::Single(input.parse()?)
             } else if val_marker == "values" {
-                Values::Multiple(input.parse()?)
+                let values = input.parse()?;
+                let mut batch_size = None;
+                if !input.is_empty() {
+                    super::validate_marker(input, "batch_size")?;
+                    batch_size = Some(input.parse()?);
+                }
+                Values::Multiple { values, batch_size }
             } else {
                 return Err(syn::Error::new(
                     val_marker.span(),
@@ -176,32 +1087,152 @@ impl Insert {
 
     fn expand(&self) -> TokenStream2 {
         let Insert { spi_client, table, values } = self;
-        let insert_string = format!("INSERT INTO {} VALUES ($1, $2)", table);
-        match values {
+        let writable_check = quote::quote_spanned! {table.span()=>
+            framework::assert_writable::<#table>();
+        };
+        let insert = match values {
             Values::Single(val) => {
                 quote! {
                     {
-                        use pgx::IntoDatum;
                         let value: #table = #val;
-                        let args = value.to_values_vec();
-                        #spi_client.update(#insert_string, None, Some(args))
+                        #spi_client.insert_rows(std::iter::once(value), 1)
                     }
                 }
             },
-            Values::Multiple(vals) => {
-                // TODO we should cache the planned query, but the APIs aren't exposed
+            Values::Multiple { values, batch_size } => {
+                let batch_size = match batch_size {
+                    Some(batch_size) => quote!{ #batch_size },
+                    None => quote!{ framework::DEFAULT_INSERT_BATCH_SIZE },
+                };
                 quote! {
-                    {
-                        use pgx::IntoDatum;
-                        let vals = #vals;
-                        for value in vals {
-                            let value: #table = value;
-                            let args = value.to_values_vec();
-                            #spi_client.update(#insert_string, None, Some(args));
-                        }
-                    }
+                    #spi_client.insert_rows::<#table>(#values, #batch_size)
                 }
             },
+        };
+        quote! {
+            {
+                use framework::client::Client as _;
+                #writable_check
+                #insert
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+// snapshots of the generated code, review changes with `cargo insta review`
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(query: Query) -> String {
+        crate::debug::pretty(&query.expand())
+    }
+
+    #[test]
+    fn select() {
+        let query: Query = syn::parse_quote! {
+            client
+            from: Example
+            select: (bar, foo)
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn select_clauses() {
+        let query: Query = syn::parse_quote! {
+            client
+            from: Jobs
+            select: (id, payload)
+            where: "NOT claimed"
+            limit: 10
+            lock: for update skip locked
+            fetch_size: 100
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn select_group_by() {
+        let query: Query = syn::parse_quote! {
+            client
+            from: Example
+            select: (bar, count(*), max(foo))
+            group by: (bar)
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn select_one() {
+        let query: Query = syn::parse_quote! {
+            client
+            from: Example
+            select_one: (foo)
+            where: "bar = 'x'"
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn exists() {
+        let query: Query = syn::parse_quote! {
+            client
+            exists: Example
+            where: "foo > 0"
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn select_distinct_on() {
+        let query: Query = syn::parse_quote! {
+            client
+            from: Example
+            select: (bar, foo)
+            distinct on: (bar)
+            order by: (bar, foo desc)
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn join_sql() {
+        let from: From = syn::parse_quote! {
+            Example join Other on Example.foo = Other.foo
+                left join Third on Other.id = Third.other_id
+        };
+        insta::assert_snapshot!(from.sql());
+    }
+
+    #[test]
+    fn select_join() {
+        let query: Query = syn::parse_quote! {
+            client
+            from: Example left join Other on Example.foo = Other.example_foo
+            select: (Example.foo, Other.name)
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn insert_value() {
+        let query: Query = syn::parse_quote! {
+            client
+            insert into: Example
+            value: Example { foo, bar }
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+
+    #[test]
+    fn insert_values() {
+        let query: Query = syn::parse_quote! {
+            client
+            insert into: Example
+            values: (0..100).map(|foo| Example { foo, bar: None })
+            batch_size: 10
+        };
+        insta::assert_snapshot!(expanded(query));
+    }
+}
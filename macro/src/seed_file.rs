@@ -0,0 +1,276 @@
+// `insert: file "seeds/units.csv"` reads the rows from a CSV or JSON file at
+// compile time, checks them against the table's columns, and turns them into
+// plain `INSERT` statements for the extension script.
+//
+// CSV files need a header row naming the columns. JSON files contain an array
+// of objects keyed by column name. Columns that are left out, empty CSV cells
+// in `Option` columns, and JSON `null`s are inserted as `NULL`.
+
+use std::path::PathBuf;
+
+pub struct SeedColumn {
+    pub name: String,
+    pub sql_type: &'static str,
+    pub nullable: bool,
+}
+
+// postgres's limit on the size of a single statement is large, but there's no
+// reason to make huge ones
+const ROWS_PER_STATEMENT: usize = 1000;
+
+// each row as a parenthesized list of SQL literals, in column order, and the
+// absolute path of the file so the caller can make the build depend on it
+pub fn rows(columns: &[SeedColumn], file: &syn::LitStr) -> syn::Result<(Vec<String>, String)> {
+    let error = |msg: String| syn::Error::new(file.span(), msg);
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| error("CARGO_MANIFEST_DIR is not set".to_string()))?;
+    let path = PathBuf::from(manifest_dir).join(file.value());
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| error(format!("cannot read `{}`: {}", path.display(), e)))?;
+
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let rows = match extension {
+        Some("csv") => csv_rows(columns, &contents),
+        Some("json") => json_rows(columns, &contents),
+        _ => Err("expected a `.csv` or `.json` file".to_string()),
+    };
+    let rows = rows.map_err(|msg| error(format!("{}: {}", file.value(), msg)))?;
+    Ok((rows, path.display().to_string()))
+}
+
+// `suffix` goes at the end of each statement, e.g. an `ON CONFLICT` clause
+pub fn insert_sql(table: &str, columns: &[String], rows: &[String], suffix: &str) -> String {
+    let mut sql = String::new();
+    for chunk in rows.chunks(ROWS_PER_STATEMENT) {
+        sql.push_str(&format!(
+            "INSERT INTO {} ({}) VALUES\n    {}{};\n",
+            table,
+            columns.join(", "),
+            chunk.join(",\n    "),
+            suffix,
+        ));
+    }
+    sql
+}
+
+// each row as a parenthesized list of SQL literals, in column order
+fn csv_rows(columns: &[SeedColumn], contents: &str) -> Result<Vec<String>, String> {
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let positions = column_positions(columns, headers.iter())?;
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let line = record.position().map_or(0, |position| position.line());
+        let values: Result<Vec<_>, _> = columns.iter().zip(&positions)
+            .map(|(column, position)| {
+                let value = position.and_then(|i| record.get(i));
+                match value {
+                    None => literal(column, Value::Null),
+                    Some("") if column.nullable => literal(column, Value::Null),
+                    Some(value) => literal(column, Value::Text(value)),
+                }
+            })
+            .collect();
+        let values = values.map_err(|msg| format!("line {}: {}", line, msg))?;
+        rows.push(format!("({})", values.join(", ")));
+    }
+    Ok(rows)
+}
+
+fn json_rows(columns: &[SeedColumn], contents: &str) -> Result<Vec<String>, String> {
+    use serde_json::Value as Json;
+
+    let json: Vec<serde_json::Map<String, Json>> = serde_json::from_str(contents)
+        .map_err(|e| format!("expected an array of objects: {}", e))?;
+
+    let mut rows = vec![];
+    for (i, object) in json.iter().enumerate() {
+        column_positions(columns, object.keys().map(|key| &**key))
+            .map_err(|msg| format!("row {}: {}", i, msg))?;
+        let values: Result<Vec<_>, _> = columns.iter()
+            .map(|column| {
+                let value = match object.get(&column.name) {
+                    None | Some(Json::Null) => Value::Null,
+                    Some(Json::String(value)) => Value::Text(value),
+                    Some(Json::Bool(value)) => Value::Bool(*value),
+                    Some(Json::Number(value)) => Value::Number(value.to_string()),
+                    Some(value) => return Err(format!(
+                        "column `{}`: `{}` is not a {}",
+                        column.name, value, column.sql_type,
+                    )),
+                };
+                literal(column, value)
+            })
+            .collect();
+        let values = values.map_err(|msg| format!("row {}: {}", i, msg))?;
+        rows.push(format!("({})", values.join(", ")));
+    }
+    Ok(rows)
+}
+
+// where each column is in the file, if it's there at all
+fn column_positions<'a>(
+    columns: &[SeedColumn],
+    names: impl Iterator<Item = &'a str>,
+) -> Result<Vec<Option<usize>>, String> {
+    let names: Vec<_> = names.collect();
+    if let Some(unknown) = names.iter().find(|name| columns.iter().all(|c| c.name != **name)) {
+        return Err(format!("unknown column `{}`", unknown))
+    }
+    Ok(columns.iter()
+        .map(|column| names.iter().position(|name| *name == column.name))
+        .collect())
+}
+
+enum Value<'v> {
+    Null,
+    // CSV cells and JSON strings
+    Text(&'v str),
+    Bool(bool),
+    Number(String),
+}
+
+// check that the value fits the column, and render it as a SQL literal
+fn literal(column: &SeedColumn, value: Value) -> Result<String, String> {
+    let mismatch = |value: &dyn std::fmt::Display| Err(format!(
+        "column `{}`: `{}` is not a valid {}",
+        column.name, value, column.sql_type,
+    ));
+
+    let text = match value {
+        Value::Null if column.nullable => return Ok("NULL".to_string()),
+        Value::Null => return Err(format!("column `{}` cannot be NULL", column.name)),
+        Value::Bool(value) if column.sql_type == "boolean" => return Ok(value.to_string()),
+        Value::Bool(value) => return mismatch(&value),
+        Value::Text(text) if column.sql_type == "text" => {
+            return Ok(format!("'{}'", text.replace('\'', "''")))
+        },
+        Value::Number(number) if column.sql_type == "text" => return mismatch(&number),
+        Value::Text(text) => text.to_string(),
+        Value::Number(number) => number,
+    };
+
+    let valid = match column.sql_type {
+        "smallint" => text.parse::<i16>().is_ok(),
+        "integer" => text.parse::<i32>().is_ok(),
+        "bigint" => text.parse::<i64>().is_ok(),
+        "oid" => text.parse::<u32>().is_ok(),
+        "real" => text.parse::<f32>().is_ok(),
+        "double precision" => text.parse::<f64>().is_ok(),
+        "boolean" => return match &*text {
+            "true" | "t" => Ok("true".to_string()),
+            "false" | "f" => Ok("false".to_string()),
+            _ => mismatch(&text),
+        },
+        _ => false,
+    };
+    if !valid {
+        return mismatch(&text)
+    }
+    // `inf` and `NaN` parse, but need quoting to be understood by postgres
+    Ok(format!("'{}'::{}", text, column.sql_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<SeedColumn> {
+        vec![
+            SeedColumn { name: "code".to_string(), sql_type: "text", nullable: false },
+            SeedColumn { name: "factor".to_string(), sql_type: "double precision", nullable: false },
+            SeedColumn { name: "label".to_string(), sql_type: "text", nullable: true },
+            SeedColumn { name: "active".to_string(), sql_type: "boolean", nullable: true },
+        ]
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let csv = "code,factor,label\n\"k,m\",1000,\"kilo \"\"metre\"\"\"\ncm,0.01,it's\n";
+        assert_eq!(csv_rows(&columns(), csv).unwrap(), vec![
+            "('k,m', '1000'::double precision, 'kilo \"metre\"', NULL)",
+            "('cm', '0.01'::double precision, 'it''s', NULL)",
+        ]);
+    }
+
+    #[test]
+    fn csv_nulls() {
+        // empty cells are NULL in nullable columns, and an empty string in text
+        // columns that aren't
+        let csv = "label,code,factor,active\n,,1,\n";
+        assert_eq!(csv_rows(&columns(), csv).unwrap(), vec![
+            "('', '1'::double precision, NULL, NULL)",
+        ]);
+    }
+
+    #[test]
+    fn csv_errors() {
+        assert_eq!(
+            csv_rows(&columns(), "code,factor\nm,one\n").unwrap_err(),
+            "line 2: column `factor`: `one` is not a valid double precision",
+        );
+        assert_eq!(
+            csv_rows(&columns(), "code,factor\nm,\n").unwrap_err(),
+            "line 2: column `factor`: `` is not a valid double precision",
+        );
+        assert_eq!(
+            csv_rows(&columns(), "code\nm\n").unwrap_err(),
+            "line 2: column `factor` cannot be NULL",
+        );
+        assert_eq!(
+            csv_rows(&columns(), "code,factor,size\nm,1,2\n").unwrap_err(),
+            "unknown column `size`",
+        );
+        assert_eq!(
+            csv_rows(&columns(), "code,factor,active\nm,1,yes\n").unwrap_err(),
+            "line 2: column `active`: `yes` is not a valid boolean",
+        );
+    }
+
+    #[test]
+    fn json_values() {
+        let json = r#"[
+            {"code": "it's", "factor": 1e3, "label": null, "active": true},
+            {"code": "cm", "factor": 0.01, "active": "f"}
+        ]"#;
+        assert_eq!(json_rows(&columns(), json).unwrap(), vec![
+            "('it''s', '1000.0'::double precision, NULL, true)",
+            "('cm', '0.01'::double precision, NULL, false)",
+        ]);
+    }
+
+    #[test]
+    fn json_errors() {
+        assert_eq!(
+            json_rows(&columns(), r#"{"code": "m"}"#).unwrap_err().split(':').next(),
+            Some("expected an array of objects"),
+        );
+        assert_eq!(
+            json_rows(&columns(), r#"[{"code": 1, "factor": 1}]"#).unwrap_err(),
+            "row 0: column `code`: `1` is not a valid text",
+        );
+        assert_eq!(
+            json_rows(&columns(), r#"[{"code": "m", "factor": [1]}]"#).unwrap_err(),
+            "row 0: column `factor`: `[1]` is not a double precision",
+        );
+        assert_eq!(
+            json_rows(&columns(), r#"[{"code": "m", "factor": null}]"#).unwrap_err(),
+            "row 0: column `factor` cannot be NULL",
+        );
+        assert_eq!(
+            json_rows(&columns(), r#"[{"code": "m", "factor": 1, "size": 2}]"#).unwrap_err(),
+            "row 0: unknown column `size`",
+        );
+    }
+
+    #[test]
+    fn statements_are_batched() {
+        let rows = vec!["(1)".to_string(); ROWS_PER_STATEMENT + 1];
+        let sql = insert_sql("Example", &["id".to_string()], &rows, " ON CONFLICT DO NOTHING");
+        assert_eq!(sql.matches("INSERT INTO Example (id) VALUES").count(), 2);
+        assert!(sql.ends_with("    (1) ON CONFLICT DO NOTHING;\n"));
+    }
+}
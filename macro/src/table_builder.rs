@@ -5,65 +5,355 @@ use quote::quote;
 
 use syn::{parse::{Parse, ParseStream}, punctuated::Punctuated, spanned::Spanned};
 
+use crate::seed_file;
+
 pub struct Table {
-    name: syn::Ident,
+    // passed through to the struct
+    attrs: Vec<syn::Attribute>,
+    vis: syn::Visibility,
+    pub(crate) name: syn::Ident,
     fields: Punctuated<Field, syn::Token![,]>,
-    insert: Option<syn::Block>,
+    insert: Option<Insert>,
     requires: Option<syn::ExprArray>,
+    sql_name: Option<syn::LitStr>,
+    verify: Option<syn::LitBool>,
+    // the extension version the table was created in, and the columns dropped
+    // since, for generating upgrade scripts
+    since: Option<syn::LitStr>,
+    dropped: Option<(syn::token::Brace, Punctuated<DroppedColumn, syn::Token![,]>)>,
+    dump: Option<Dump>,
+    // `key: (a, b)`, the primary key
+    key: Option<(syn::token::Paren, Punctuated<syn::Ident, syn::Token![,]>)>,
+}
+
+struct Insert {
+    rows: SeedRows,
+    // `on conflict do nothing|update`
+    on_conflict: Option<(syn::Ident, OnConflict)>,
+}
+
+enum SeedRows {
+    // an expression producing an iterator of tuples, run at install time
+    Block(syn::Block),
+    // `file "seeds/rows.csv"`, relative to the crate root
+    File(syn::LitStr),
+}
+
+enum OnConflict {
+    Nothing,
+    Update,
+}
+
+// which rows `pg_dump` should include
+enum Dump {
+    All(syn::Ident),
+    Where(syn::LitStr),
 }
 
 struct Field {
+    // passed through to the struct field
+    attrs: Vec<syn::Attribute>,
+    vis: syn::Visibility,
     name: syn::Ident,
     ty: syn::TypePath,
+    // `#[since = "1.1"]`
+    since: Option<syn::LitStr>,
+    // `#[default = "0"]`, the value existing rows get when the column is added
+    default: Option<syn::LitStr>,
+    // `#[changed("1.2", from = i32)]`, in the order they happened
+    changes: Vec<TypeChange>,
+}
+
+struct TypeChange {
+    version: syn::LitStr,
+    from: syn::TypePath,
+}
+
+// `dropped: { column: Option<String> in "1.2" }`. Dropped columns are taken to
+// have existed since the table was created
+struct DroppedColumn {
+    name: syn::Ident,
+    ty: syn::TypePath,
+    version: syn::LitStr,
 }
 
 impl Parse for Table {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let vis = input.parse()?;
         let name = input.parse()?;
         let content;
         let _ = syn::parenthesized!(content in input);
         let fields = Punctuated::parse_terminated(&content)?;
 
-        let mut insert: Option<syn::Block> = None;
-        let mut requires: Option<syn::ExprArray> = None;
+        let mut table = Self::new(attrs, vis, name, fields);
+        table.parse_markers(input)?;
+        Ok(table)
+    }
+}
+
+impl Table {
+    fn new(
+        attrs: Vec<syn::Attribute>,
+        vis: syn::Visibility,
+        name: syn::Ident,
+        fields: Punctuated<Field, syn::Token![,]>,
+    ) -> Self {
+        Self {
+            attrs,
+            vis,
+            name,
+            fields,
+            insert: None,
+            requires: None,
+            sql_name: None,
+            verify: None,
+            since: None,
+            dropped: None,
+            dump: None,
+            key: None,
+        }
+    }
+
+    // `#[pg_table(markers...)] struct Name { fields... }`
+    pub fn from_struct(args: TokenStream2, item: syn::ItemStruct) -> syn::Result<Self> {
+        use syn::parse::Parser;
+
+        if !item.generics.params.is_empty() || item.generics.where_clause.is_some() {
+            return Err(syn::Error::new(
+                item.generics.span(),
+                "tables cannot be generic",
+            ))
+        }
+        let named = match item.fields {
+            syn::Fields::Named(named) => named,
+            fields => return Err(syn::Error::new(
+                fields.span(),
+                "tables need named fields",
+            )),
+        };
+        let fields = named.named.into_iter()
+            .map(|field| {
+                let ty = match field.ty {
+                    syn::Type::Path(ty) => ty,
+                    ty => return Err(syn::Error::new(ty.span(), "unsupported column type")),
+                };
+                Field::new(field.attrs, field.vis, field.ident.unwrap(), ty)
+            })
+            .collect::<syn::Result<_>>()?;
+
+        let mut table = Self::new(item.attrs, item.vis, item.ident, fields);
+        (|input: ParseStream| table.parse_markers(input)).parse2(args)?;
+        Ok(table)
+    }
+
+    fn parse_markers(&mut self, input: ParseStream) -> syn::Result<()> {
+        let Self { insert, requires, sql_name, verify, since, dropped, dump, key, .. } = self;
         super::parse_marked(input, [
             ("insert", &mut |input| {
-                if insert.is_some() {
-                    panic!("duplicate `insert`")
-                }
-                insert = Some(input.parse()?);
+                *insert = Some(input.parse()?);
                 Ok(())
             }),
             ("requires", &mut |input| {
-                if requires.is_some() {
-                    panic!("duplicate `requires`")
-                }
-                requires = Some(input.parse()?);
+                *requires = Some(input.parse()?);
                 Ok(())
-            })
-        ])?;
+            }),
+            ("name", &mut |input| {
+                *sql_name = Some(input.parse()?);
+                Ok(())
+            }),
+            ("verify", &mut |input| {
+                *verify = Some(input.parse()?);
+                Ok(())
+            }),
+            ("since", &mut |input| {
+                let version = input.parse()?;
+                validate_version(&version)?;
+                *since = Some(version);
+                Ok(())
+            }),
+            ("dropped", &mut |input| {
+                let content;
+                let braces = syn::braced!(content in input);
+                *dropped = Some((braces, Punctuated::parse_terminated(&content)?));
+                Ok(())
+            }),
+            ("dump", &mut |input| {
+                *dump = Some(input.parse()?);
+                Ok(())
+            }),
+            ("key", &mut |input| {
+                let content;
+                let parens = syn::parenthesized!(content in input);
+                *key = Some((parens, Punctuated::parse_terminated(&content)?));
+                Ok(())
+            }),
+        ])
+    }
+}
+
+impl Parse for Insert {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let rows = if super::peek_word(input, "file") {
+            super::parse_word(input, "file")?;
+            SeedRows::File(input.parse()?)
+        } else {
+            SeedRows::Block(input.parse()?)
+        };
+
+        let mut on_conflict = None;
+        if super::peek_word(input, "on") {
+            let on = super::parse_word(input, "on")?;
+            super::parse_word(input, "conflict")?;
+            let _: syn::Token![do] = input.parse()?;
+            let action = if super::peek_word(input, "nothing") {
+                super::parse_word(input, "nothing")?;
+                OnConflict::Nothing
+            } else {
+                super::parse_word(input, "update")
+                    .map_err(|error| syn::Error::new(error.span(), "expected `nothing` or `update`"))?;
+                OnConflict::Update
+            };
+            on_conflict = Some((on, action));
+        }
 
         Ok(Self {
-            name,
-            fields,
-            insert,
-            requires,
+            rows,
+            on_conflict,
         })
     }
 }
 
+impl Insert {
+    fn span(&self) -> proc_macro2::Span {
+        match &self.rows {
+            SeedRows::Block(body) => body.span(),
+            SeedRows::File(file) => file.span(),
+        }
+    }
+}
+
+impl Parse for Dump {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::Token![where]) {
+            let _: syn::Token![where] = input.parse()?;
+            return Ok(Self::Where(input.parse()?))
+        }
+        let all = super::parse_word(input, "all")
+            .map_err(|error| syn::Error::new(error.span(), "expected `all` or `where \"...\"`"))?;
+        Ok(Self::All(all))
+    }
+}
+
 impl Parse for Field {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let name = input.parse()?;
+        let _: syn::Token![:] = input.parse()?;
+        let ty = input.parse()?;
+        Self::new(attrs, vis, name, ty)
+    }
+}
+
+impl Field {
+    // the versioning attributes are ours, anything else is kept for the struct
+    fn new(
+        attrs: Vec<syn::Attribute>,
+        vis: syn::Visibility,
+        name: syn::Ident,
+        ty: syn::TypePath,
+    ) -> syn::Result<Self> {
+        let mut since = None;
+        let mut default = None;
+        let mut changes = vec![];
+        let mut passed_through = vec![];
+        for attr in attrs {
+            if attr.path.is_ident("since") {
+                let version = attr_value(&attr)?;
+                validate_version(&version)?;
+                since = Some(version);
+            } else if attr.path.is_ident("default") {
+                default = Some(attr_value(&attr)?);
+            } else if attr.path.is_ident("changed") {
+                changes.push(attr.parse_args()?);
+            } else {
+                passed_through.push(attr);
+            }
+        }
+
+        Ok(Self {
+            attrs: passed_through,
+            vis,
+            name,
+            ty,
+            since,
+            default,
+            changes,
+        })
+    }
+}
+
+impl Parse for TypeChange {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let version = input.parse()?;
+        validate_version(&version)?;
+        let _: syn::Token![,] = input.parse()?;
+        super::parse_word(input, "from")?;
+        let _: syn::Token![=] = input.parse()?;
+        let from = input.parse()?;
+        Ok(Self {
+            version,
+            from,
+        })
+    }
+}
+
+impl Parse for DroppedColumn {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let name = input.parse()?;
         let _: syn::Token![:] = input.parse()?;
         let ty = input.parse()?;
+        let _: syn::Token![in] = input.parse()?;
+        let version = input.parse()?;
+        validate_version(&version)?;
         Ok(Self {
             name,
             ty,
+            version,
         })
     }
 }
 
+// `#[name = "value"]`
+fn attr_value(attr: &syn::Attribute) -> syn::Result<syn::LitStr> {
+    match attr.parse_meta()? {
+        syn::Meta::NameValue(syn::MetaNameValue { lit: syn::Lit::Str(value), .. }) =>
+            Ok(value),
+        meta => Err(syn::Error::new(
+            meta.span(),
+            "expected a string, e.g. `#[since = \"1.1\"]`",
+        )),
+    }
+}
+
+// versions are compared component-wise, so they must be dot-separated numbers
+fn validate_version(version: &syn::LitStr) -> syn::Result<()> {
+    let value = version.value();
+    let valid = value.split('.').all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+    if !valid {
+        return Err(syn::Error::new(
+            version.span(),
+            format!("`{}` is not a version, expected something like `1.1`", value),
+        ))
+    }
+    Ok(())
+}
+
+fn version_key(version: &syn::LitStr) -> Vec<u64> {
+    version.value().split('.').map(|part| part.parse().unwrap()).collect()
+}
+
 
 //
 //
@@ -71,14 +361,60 @@ impl Parse for Field {
 //
 
 pub fn expand(agg: Table) -> TokenStream2 {
+    expand_table(agg).unwrap_or_else(|error| error.to_compile_error())
+}
+
+fn expand_table(agg: Table) -> syn::Result<TokenStream2> {
     use std::fmt::Write;
 
-    let Table{ name, fields, insert, requires } = agg;
-    let struct_fields = fields.iter().map(|Field { name, ty }| quote!{
-        #name: #ty,
+    let Table{ attrs, vis, name, fields, insert, requires, sql_name, verify, since, dropped, dump, key } = agg;
+    if let Some(sql_name) = sql_name {
+        return Err(syn::Error::new(
+            sql_name.span(),
+            "`name` is only supported on external tables",
+        ))
+    }
+
+    let key: Vec<&syn::Ident> = key.iter().flat_map(|(_, key)| key.iter()).collect();
+    let unknown_key = key.iter().find(|column| fields.iter().all(|field| &field.name != **column));
+    if let Some(column) = unknown_key {
+        return Err(syn::Error::new(
+            column.span(),
+            format!("`{}` is not a column of `{}`", column, name),
+        ))
+    }
+    // primary key columns are always NOT NULL
+    let optional_key = key.iter().find(|column| fields.iter().any(|field| {
+        &field.name == **column
+            && matches!(field.ty.path.segments.last(), Some(end) if end.ident == "Option")
+    }));
+    if let Some(column) = optional_key {
+        return Err(syn::Error::new(
+            column.span(),
+            format!("key column `{}` cannot be an `Option`, primary keys are never NULL", column),
+        ))
+    }
+    let key: Vec<String> = key.iter().map(|column| column.to_string()).collect();
+
+    let on_conflict = insert.as_ref().and_then(|insert| insert.on_conflict.as_ref());
+    let conflict_clause = match on_conflict {
+        None => String::new(),
+        Some((on, _)) if key.is_empty() => return Err(syn::Error::new(
+            on.span(),
+            "`on conflict` needs the table to have a `key`",
+        )),
+        Some((_, action)) => conflict_clause(&key, &fields, action),
+    };
+
+    let struct_fields = fields.iter().map(|Field { attrs, vis, name, ty, .. }| quote!{
+        #(#attrs)*
+        #vis #name: #ty,
     });
 
-    let table_fields = sql_fields(&fields);
+    let mut table_fields = sql_fields(&fields)?;
+    if !key.is_empty() {
+        let _ = write!(&mut table_fields, ",\n    PRIMARY KEY ({})", key.join(", "));
+    }
 
     let mut create_table = format!("\
             CREATE TABLE {name} (\n\
@@ -89,64 +425,137 @@ pub fn expand(agg: Table) -> TokenStream2 {
         fields=table_fields
     );
 
-    let mod_name = super::table_mod(&name);
-    let field_types = fields.iter().map(|Field {name, ty}| {
-        let optional_name = super::optional_name(name);
-        let optional_ty = option_type(ty);
-        quote! {
-            pub type #name = #ty;
-            pub type #optional_name = #optional_ty;
+    // recorded before the comments and seeding statements are appended
+    let create_table_only = create_table.clone();
+
+    if let Some(comment) = doc_comment(&attrs) {
+        let _ = writeln!(&mut create_table, "COMMENT ON TABLE {} IS {};", name, comment);
+    }
+    for field in &fields {
+        if let Some(comment) = doc_comment(&field.attrs) {
+            let _ = writeln!(
+                &mut create_table,
+                "COMMENT ON COLUMN {}.{} IS {};",
+                name, field.name, comment,
+            );
         }
-    });
+    }
+
+    let type_mod = type_mod(&vis, &name, &fields);
+    let verify = matches!(&verify, Some(verify) if verify.value)
+        .then(|| quote!(framework::schema::verify_schema));
+    let dropped = dropped.map(|(_, dropped)| dropped);
+    let history = history(&name, &fields, &key, since.as_ref(), dropped.as_ref())?;
+    let table_impl = table_impl(&name, &name.to_string(), &fields, &key, verify, history);
 
     let field_ty = fields.iter().map(|Field {ty, ..}| ty);
     let field_name = fields.iter().map(|Field {name, ..}| name);
     let field = fields.iter().map(|Field {name, ..}| name);
 
 
-    let table_insert_function = insert.as_ref().map(|body| {
-        let table_insert = syn::Ident::new(
-            &format!("__table_builder_insert_{}", name),
-            // TODO should this be def_site?
-            body.span(),
-        );
-        let _ = write!(&mut create_table, "\
-            INSERT INTO {table} SELECT * FROM \"{insert_fn}\"();\n\
-            DROP FUNCTION \"{insert_fn}\";\n",
-            table = name,
-            insert_fn = table_insert,
-        );
-        let return_ty = fields.iter().map(|Field {name, ty}| quote! {
-            pgx::name!(#name,#ty)
-        });
-        quote!{
-            // hack b/c this doesn't understand the schema markers
-            #[pg_extern(schema="public")]
-            pub fn #table_insert() -> impl Iterator<Item = (#(#return_ty),*)> #body
-        }
-    });
+    // the seed rows either come from a C function that the extension script
+    // creates in `pg_temp` while it needs it, or are read from a file now, in
+    // which case the file is included so that changing it rebuilds the
+    // extension
+    let mut seed_source = None;
+    let mut seed_function = None;
+    let mut seed_file = None;
+    match insert.as_ref().map(|insert| &insert.rows) {
+        None => (),
+        Some(SeedRows::Block(body)) => {
+            let seed_fn = syn::Ident::new(
+                &format!("__table_builder_insert_{}", name),
+                // TODO should this be def_site?
+                body.span(),
+            );
+            let finfo = syn::Ident::new(&format!("pg_finfo_{}", seed_fn), body.span());
+            let field = fields.iter().map(|Field {name, ..}| name);
+            let field_name = fields.iter().map(|Field {name, ..}| name);
+            // a `(text, text) RETURNS void` function, taking the table to insert
+            // into and the end of the `INSERT` statement
+            seed_function = Some(quote!{
+                #[no_mangle]
+                #[doc(hidden)]
+                pub extern "C" fn #finfo() -> &'static pgx::pg_sys::Pg_finfo_record {
+                    const V1_API: pgx::pg_sys::Pg_finfo_record =
+                        pgx::pg_sys::Pg_finfo_record { api_version: 1 };
+                    &V1_API
+                }
 
-    let create_table_name = format!("__CREATE_TABLE_{}", name);
+                #[no_mangle]
+                #[doc(hidden)]
+                #[pgx::pg_guard]
+                pub extern "C" fn #seed_fn(
+                    fcinfo: pgx::pg_sys::FunctionCallInfo,
+                ) -> pgx::pg_sys::Datum {
+                    let table: String = pgx::pg_getarg(fcinfo, 0).unwrap();
+                    let suffix: String = pgx::pg_getarg(fcinfo, 1).unwrap();
+                    let rows = #body;
+                    framework::seed_rows(
+                        &table,
+                        &suffix,
+                        rows.map(|(#(#field),*)| {
+                            framework::PgWritableTable::to_values_vec(#name { #(#field_name),* })
+                        }),
+                    );
+                    0
+                }
+            });
+            seed_source = Some(SeedSource::Function(seed_fn.to_string()));
+        },
+        Some(SeedRows::File(file)) => {
+            let columns: Vec<_> = fields.iter()
+                .map(|Field { name, ty, .. }| {
+                    let (sql_type, nullable) = sql_type(ty)?;
+                    Ok(seed_file::SeedColumn { name: name.to_string(), sql_type, nullable })
+                })
+                .collect::<syn::Result<_>>()?;
+            let (rows, path) = seed_file::rows(&columns, file)?;
+            seed_file = Some(quote! {
+                const _: &[u8] = include_bytes!(#path);
+            });
+            let columns = columns.into_iter().map(|column| column.name).collect();
+            seed_source = Some(SeedSource::Values(columns, rows));
+        },
+    }
 
-    quote! {
-        struct #name {
-            #(#struct_fields)*
+    if let Some(source) = &seed_source {
+        create_table.push_str(&source.create());
+        create_table.push_str(&source.insert(&name.to_string(), &conflict_clause));
+        create_table.push_str(&source.drop());
+    }
+
+    if let Some(dump) = &dump {
+        let _ = write!(&mut create_table, "{}", dump_sql(&name, dump, insert.is_some()));
+    }
+
+    // tables seeded with `on conflict` can safely be reseeded by every upgrade
+    let mut seed = String::new();
+    if let (Some(source), Some(_)) = (&seed_source, on_conflict) {
+        seed.push_str(&source.create());
+        seed.push_str(&source.insert(&name.to_string(), &conflict_clause));
+        if dump.is_some() {
+            let seed_table = seed_table(&name);
+            let _ = writeln!(&mut seed, "DELETE FROM {};", seed_table);
+            seed.push_str(&source.insert(&seed_table, ""));
         }
+        seed.push_str(&source.drop());
+    }
 
-        unsafe impl framework::PgTable for #name {}
+    let create_table_name = format!("__CREATE_TABLE_{}", name);
 
-        // inherent associated types are unstable, so fake it with a mod
-        #[allow(non_snake_case)]
-        #[allow(non_camel_case_types)]
-        mod #mod_name {
-            #(#field_types)*
+    Ok(quote! {
+        #(#attrs)*
+        #vis struct #name {
+            #(#struct_fields)*
         }
 
-        #[allow(non_snake_case)]
-        #table_insert_function
+        #table_impl
+        unsafe impl framework::PgWritableTable for #name {
+            const CREATE: &'static str = #create_table_only;
+            const SEED: &'static str = #seed;
 
-        impl #name {
-            pub fn to_values_vec(self) -> Vec<(pgx::PgOid, Option<pgx::pg_sys::Datum>)> {
+            fn to_values_vec(self) -> Vec<(pgx::PgOid, Option<pgx::pg_sys::Datum>)> {
                 use pgx::IntoDatum;
                 let Self{ #(#field_name),* } = self;
                 vec![
@@ -158,45 +567,555 @@ pub fn expand(agg: Table) -> TokenStream2 {
             }
         }
 
+        #type_mod
+
+        #[allow(non_snake_case)]
+        #seed_function
+        #seed_file
+
+        impl #name {
+            pub fn copy_in<I: IntoIterator<Item = Self>>(rows: I) -> u64 {
+                framework::copy::copy_in(
+                    <Self as framework::PgTable>::NAME,
+                    rows.into_iter().map(<Self as framework::PgWritableTable>::to_values_vec),
+                )
+            }
+
+            pub fn verify_schema(
+                client: &pgx::SpiClient,
+            ) -> Result<(), framework::schema::SchemaDiff> {
+                framework::schema::verify_schema::<Self>(client)
+            }
+        }
+
         pgx::extension_sql! {
             #create_table,
             name = #create_table_name,
         }
+    })
+}
+
+enum SeedSource {
+    // the name of the C function inserting the rows
+    Function(String),
+    // the columns, and each row as a parenthesized list of SQL literals
+    Values(Vec<String>, Vec<String>),
+}
+
+impl SeedSource {
+    fn create(&self) -> String {
+        match self {
+            Self::Function(seed_fn) => format!(
+                "CREATE FUNCTION pg_temp.\"{seed_fn}\"(text, text) RETURNS void \
+                LANGUAGE c AS 'MODULE_PATHNAME', '{seed_fn}';\n",
+                seed_fn = seed_fn,
+            ),
+            Self::Values(..) => String::new(),
+        }
+    }
+
+    fn insert(&self, table: &str, suffix: &str) -> String {
+        match self {
+            Self::Function(seed_fn) => format!(
+                "SELECT pg_temp.\"{}\"('{}', '{}');\n",
+                seed_fn, table, suffix.replace('\'', "''"),
+            ),
+            Self::Values(columns, rows) => seed_file::insert_sql(table, columns, rows, suffix),
+        }
+    }
+
+    fn drop(&self) -> String {
+        match self {
+            Self::Function(seed_fn) => format!("DROP FUNCTION pg_temp.\"{}\";\n", seed_fn),
+            Self::Values(..) => String::new(),
+        }
+    }
+}
+
+fn conflict_clause(
+    key: &[String],
+    fields: &Punctuated<Field, syn::Token![,]>,
+    action: &OnConflict,
+) -> String {
+    let updates: Vec<_> = fields.iter()
+        .filter(|field| !key.iter().any(|column| field.name == column))
+        .map(|field| format!("{column} = EXCLUDED.{column}", column = field.name))
+        .collect();
+    match action {
+        OnConflict::Update if !updates.is_empty() => format!(
+            " ON CONFLICT ({}) DO UPDATE SET {}",
+            key.join(", "),
+            updates.join(", "),
+        ),
+        // with nothing but the key there's nothing to update
+        _ => format!(" ON CONFLICT ({}) DO NOTHING", key.join(", ")),
+    }
+}
+
+// tables that exist independently of this extension, e.g. the builtin
+// catalogs, can be read from but neither created nor written to
+pub fn expand_extern(table: Table) -> TokenStream2 {
+    expand_extern_table(table).unwrap_or_else(|error| error.to_compile_error())
+}
+
+fn expand_extern_table(table: Table) -> syn::Result<TokenStream2> {
+    let Table{ attrs, vis, name, fields, insert, requires, sql_name, verify, since, dropped, dump, key } = table;
+    if let Some(insert) = insert {
+        return Err(syn::Error::new(
+            insert.span(),
+            "cannot `insert` into an external table",
+        ))
+    }
+    if let Some(requires) = requires {
+        return Err(syn::Error::new(
+            requires.span(),
+            "external tables cannot have `requires`",
+        ))
+    }
+    if let Some(since) = since {
+        return Err(syn::Error::new(
+            since.span(),
+            "external tables are not versioned with the extension",
+        ))
+    }
+    if let Some((braces, _)) = dropped {
+        return Err(syn::Error::new(
+            braces.span,
+            "external tables are not versioned with the extension",
+        ))
+    }
+    if let Some((parens, _)) = key {
+        return Err(syn::Error::new(
+            parens.span,
+            "external tables cannot declare a `key`",
+        ))
+    }
+    if let Some(dump) = dump {
+        let span = match dump {
+            Dump::All(all) => all.span(),
+            Dump::Where(filter) => filter.span(),
+        };
+        return Err(syn::Error::new(
+            span,
+            "external tables are not dumped with the extension",
+        ))
+    }
+    let versioned = fields.iter()
+        .find(|field| field.since.is_some() || field.default.is_some() || !field.changes.is_empty());
+    if let Some(field) = versioned {
+        return Err(syn::Error::new(
+            field.name.span(),
+            "external tables are not versioned with the extension",
+        ))
+    }
+
+    let struct_fields = fields.iter().map(|Field { attrs, vis, name, ty, .. }| quote!{
+        #(#attrs)*
+        #vis #name: #ty,
+    });
+    let type_mod = type_mod(&vis, &name, &fields);
+    let sql_name = sql_name.map_or_else(|| name.to_string(), |sql_name| sql_name.value());
+    let verify = matches!(&verify, Some(verify) if verify.value)
+        .then(|| quote!(framework::schema::check_extern_table));
+    let table_impl = table_impl(&name, &sql_name, &fields, &[], verify, vec![]);
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis struct #name {
+            #(#struct_fields)*
+        }
+
+        #table_impl
+
+        #type_mod
+    })
+}
+
+fn table_impl(
+    name: &syn::Ident,
+    sql_name: &str,
+    fields: &Punctuated<Field, syn::Token![,]>,
+    key: &[String],
+    verify: Option<TokenStream2>,
+    history: Vec<(String, String)>,
+) -> TokenStream2 {
+    let mod_name = super::table_mod(name);
+    let columns = fields.iter().map(|Field { name, .. }| {
+        let column_name = name.to_string();
+        quote! {
+            framework::Column {
+                name: #column_name,
+                sql_type: <#mod_name::#name as framework::PgTyped>::SQL_TYPE,
+                nullable: <#mod_name::#name as framework::PgTyped>::NULLABLE,
+            }
+        }
+    });
+
+    // tables can be checked against the catalog before we first read from
+    // them, strictly for our own tables and loosely for external ones
+    let before_query = verify.map(|verify| quote! {
+        fn before_query(client: &pgx::SpiClient) {
+            use std::sync::atomic::{AtomicBool, Ordering};
+            static VERIFIED: AtomicBool = AtomicBool::new(false);
+            if VERIFIED.load(Ordering::Relaxed) {
+                return
+            }
+            if let Err(diff) = #verify::<Self>(client) {
+                pgx::error!("{}", diff)
+            }
+            VERIFIED.store(true, Ordering::Relaxed);
+        }
+    });
+
+    let history = history.iter().map(|(version, sql)| quote! {
+        framework::upgrade::Change { version: #version, sql: #sql }
+    });
+    let field_names: Vec<_> = fields.iter().map(|Field { name, .. }| name).collect();
+
+    quote! {
+        unsafe impl framework::PgTable for #name {
+            const NAME: &'static str = #sql_name;
+            const COLUMNS: &'static [framework::Column] = &[#(#columns),*];
+            const KEY: &'static [&'static str] = &[#(#key),*];
+            const HISTORY: &'static [framework::upgrade::Change] = &[#(#history),*];
+
+            #before_query
+
+            fn into_values(self) -> Vec<framework::client::Value> {
+                let Self { #(#field_names),* } = self;
+                vec![#(#field_names.into()),*]
+            }
+        }
+    }
+}
+
+fn type_mod(
+    vis: &syn::Visibility,
+    name: &syn::Ident,
+    fields: &Punctuated<Field, syn::Token![,]>,
+) -> TokenStream2 {
+    let mod_name = super::table_mod(name);
+    let field_types = fields.iter().map(|Field {attrs, name, ty, ..}| {
+        let optional_name = super::optional_name(name);
+        let optional_ty = option_type(ty);
+        // derives and the like don't apply to type aliases, but docs do
+        let docs = attrs.iter().filter(|attr| attr.path.is_ident("doc"));
+        quote! {
+            #(#docs)*
+            pub type #name = #ty;
+            pub type #optional_name = #optional_ty;
+        }
+    });
+    // `query!` looks columns up through this rather than naming the aliases
+    // directly, so that a typo is reported as an unknown column instead of a
+    // missing type in a mod the user never wrote
+    let column_arms = fields.iter().map(|Field {name, ..}| {
+        let optional_name = super::optional_name(name);
+        quote! {
+            (#name) => { #mod_name::#name };
+            (optional #name) => { #mod_name::#optional_name };
+        }
+    });
+    let unknown_column = format!("` in table `{}`", name);
+    let column_arms = quote! {
+        #(#column_arms)*
+        ($column:ident) => {
+            compile_error!(concat!("no column `", stringify!($column), #unknown_column))
+        };
+        (optional $column:ident) => {
+            compile_error!(concat!("no column `", stringify!($column), #unknown_column))
+        };
+    };
+    // a `macro_rules!` can only be used outside its crate once exported, which
+    // puts it in the crate root, so public tables' macros need a name of their
+    // own. Two public tables with the same name in one crate clash there
+    let column_macro = match vis {
+        syn::Visibility::Public(_) => {
+            let exported = quote::format_ident!("__table_builder_column_{}", name);
+            quote! {
+                #[doc(hidden)]
+                #[macro_export]
+                macro_rules! #exported {
+                    #column_arms
+                }
+                #[allow(unused_imports)]
+                pub use #exported as __column;
+            }
+        },
+        _ => quote! {
+            #[allow(unused_macros)]
+            macro_rules! __column {
+                #column_arms
+            }
+            #[allow(unused_imports)]
+            pub(crate) use __column;
+        },
+    };
+
+    quote! {
+        // inherent associated types are unstable, so fake it with a mod
+        #[allow(non_snake_case)]
+        #[allow(non_camel_case_types)]
+        #vis mod #mod_name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#field_types)*
+
+            #column_macro
+        }
     }
 }
 
-fn sql_fields(fields: &Punctuated<Field, syn::Token![,]>) -> String {
+// tables belonging to the extension are skipped by `pg_dump`, unless they're
+// marked as config tables. The seed rows will be recreated along with the table
+// on restore, so they're copied into a shadow table and filtered out. `pg_dump`
+// runs the filter with an empty `search_path`, so the tables in it are
+// qualified with the extension's schema
+fn dump_sql(table: &syn::Ident, dump: &Dump, seeded: bool) -> String {
+    use std::fmt::Write as _;
+
+    let mut sql = String::new();
+    let mut conditions = vec![];
+    if let Dump::Where(filter) = dump {
+        conditions.push(format!("({})", filter.value()));
+    }
+    if seeded {
+        let seed_table = seed_table(table);
+        let _ = write!(
+            &mut sql,
+            "CREATE TABLE {seed} (LIKE {table});\n\
+            INSERT INTO {seed} SELECT * FROM {table};\n",
+            seed = seed_table,
+            table = table,
+        );
+        conditions.push(format!(
+            "NOT EXISTS (SELECT FROM @extschema@.{seed} \
+            WHERE ROW(@extschema@.{seed}.*) IS NOT DISTINCT FROM ROW(@extschema@.{table}.*))",
+            seed = seed_table,
+            table = table,
+        ));
+    }
+
+    let filter = match &*conditions {
+        [] => String::new(),
+        conditions => format!("WHERE {}", conditions.join(" AND ")),
+    };
+    let _ = writeln!(
+        &mut sql,
+        "SELECT pg_catalog.pg_extension_config_dump('{}', '{}');",
+        table,
+        filter.replace('\'', "''"),
+    );
+    sql
+}
+
+// doc comments as a quoted SQL string, for `COMMENT ON`
+fn doc_comment(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs.iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue { lit: syn::Lit::Str(doc), .. })) =>
+                Some(doc.value()),
+            _ => None,
+        })
+        .flat_map(|doc| doc.lines().map(|line| line.trim().to_string()).collect::<Vec<_>>())
+        .collect();
+    let comment = lines.join("\n");
+    let comment = comment.trim();
+    if comment.is_empty() {
+        return None
+    }
+    Some(format!("'{}'", comment.replace('\'', "''")))
+}
+
+fn seed_table(table: &syn::Ident) -> String {
+    format!("__table_builder_seed_{}", table)
+}
+
+// the SQL needed to bring the table from each previous version of the extension
+// to the next, in version order. Each version's statements are applied to the
+// table as it was at the previous version, so columns are declared with the
+// type they had at the time
+fn history(
+    table: &syn::Ident,
+    fields: &Punctuated<Field, syn::Token![,]>,
+    key: &[String],
+    since: Option<&syn::LitStr>,
+    dropped: Option<&Punctuated<DroppedColumn, syn::Token![,]>>,
+) -> syn::Result<Vec<(String, String)>> {
+    use std::fmt::Write as _;
+
+    let mut history = vec![];
+
+    let added_later = |field: &Field| match (&field.since, since) {
+        (Some(field_since), Some(since)) => version_key(field_since) > version_key(since),
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+
+    let key_added_later = fields.iter()
+        .any(|field| key.contains(&field.name.to_string()) && added_later(field));
+
+    if let Some(since) = since {
+        // anything added or changed after the table was created is applied by
+        // later versions
+        let mut after_since = version_key(since);
+        after_since.push(0);
+        let mut columns = String::new();
+        for field in fields.iter().filter(|field| !added_later(field)) {
+            if !columns.is_empty() {
+                columns.push_str(",\n");
+            }
+            let ty = type_before(field, &after_since);
+            let _ = write!(&mut columns, "    {} {}", field.name, sql_field_ty(ty)?);
+        }
+        for column in dropped.into_iter().flatten() {
+            let _ = write!(&mut columns, ",\n    {} {}", column.name, sql_field_ty(&column.ty)?);
+        }
+        // a key that includes a column added later is added along with it
+        if !key.is_empty() && !key_added_later {
+            let _ = write!(&mut columns, ",\n    PRIMARY KEY ({})", key.join(", "));
+        }
+        history.push((
+            since.value(),
+            format!("CREATE TABLE {} (\n{}\n);\n", table, columns),
+        ));
+    }
+
+    for field in fields {
+        if let Some(field_since) = field.since.as_ref().filter(|_| added_later(field)) {
+            let mut after_since = version_key(field_since);
+            after_since.push(0);
+            let ty = type_before(field, &after_since);
+            let mut sql = format!(
+                "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {}",
+                table, field.name, sql_field_ty(ty)?,
+            );
+            match &field.default {
+                Some(default) => {
+                    let _ = write!(
+                        &mut sql,
+                        " DEFAULT {};\nALTER TABLE {} ALTER COLUMN {} DROP DEFAULT;\n",
+                        default.value(), table, field.name,
+                    );
+                },
+                None if !sql_type(ty)?.1 => return Err(syn::Error::new(
+                    field.name.span(),
+                    "non-`Option` columns added to an existing table need a `#[default]`",
+                )),
+                None => sql.push_str(";\n"),
+            }
+            history.push((field_since.value(), sql));
+        }
+
+        for (i, change) in field.changes.iter().enumerate() {
+            let (from, from_nullable) = sql_type(&change.from)?;
+            let to = field.changes.get(i + 1).map_or(&field.ty, |next| &next.from);
+            let (to, to_nullable) = sql_type(to)?;
+            let mut sql = String::new();
+            if from != to {
+                let _ = writeln!(
+                    &mut sql,
+                    "ALTER TABLE {table} ALTER COLUMN {column} TYPE {ty} USING {column}::{ty};",
+                    table = table, column = field.name, ty = to,
+                );
+            }
+            match (from_nullable, to_nullable) {
+                (true, false) => {
+                    let _ = writeln!(
+                        &mut sql,
+                        "ALTER TABLE {} ALTER COLUMN {} SET NOT NULL;",
+                        table, field.name,
+                    );
+                },
+                (false, true) => {
+                    let _ = writeln!(
+                        &mut sql,
+                        "ALTER TABLE {} ALTER COLUMN {} DROP NOT NULL;",
+                        table, field.name,
+                    );
+                },
+                _ => (),
+            }
+            history.push((change.version.value(), sql));
+        }
+    }
+
+    if since.is_some() && key_added_later {
+        let version = fields.iter()
+            .filter(|field| key.contains(&field.name.to_string()))
+            .filter_map(|field| field.since.as_ref())
+            .max_by_key(|version| version_key(version))
+            .unwrap();
+        history.push((
+            version.value(),
+            format!("ALTER TABLE {} ADD PRIMARY KEY ({});\n", table, key.join(", ")),
+        ));
+    }
+
+    for column in dropped.into_iter().flatten() {
+        history.push((
+            column.version.value(),
+            format!("ALTER TABLE {} DROP COLUMN IF EXISTS {};\n", table, column.name),
+        ));
+    }
+
+    // the sort is stable, so statements for the same version stay in the
+    // order they were declared in
+    history.sort_by_cached_key(|(version, _)| {
+        version.split('.').map(|part| part.parse::<u64>().unwrap()).collect::<Vec<_>>()
+    });
+    Ok(history)
+}
+
+// the type a column had just before `version`
+fn type_before<'f>(field: &'f Field, version: &[u64]) -> &'f syn::TypePath {
+    field.changes.iter()
+        .find(|change| version_key(&change.version).as_slice() >= version)
+        .map_or(&field.ty, |change| &change.from)
+}
+
+fn sql_fields(fields: &Punctuated<Field, syn::Token![,]>) -> syn::Result<String> {
     use std::fmt::Write as _;
 
     let mut sql = String::new();
     let mut is_first = true;
-    for Field { name, ty } in fields {
+    for Field { name, ty, .. } in fields {
         if !is_first {
             sql.push_str(",\n");
         }
         is_first = false;
-        let _ = write!(&mut sql, "    {} {}", name, sql_field_ty(ty));
+        let _ = write!(&mut sql, "    {} {}", name, sql_field_ty(ty)?);
     }
-    sql
+    Ok(sql)
 }
 
 // TODO a better way to do this would be `<#ty as PgTyped>::TY` but that would
 //      require both SQL type info in a trait, and dynamic `extension_sql!`
 //      strings, neither of which exists yet. Instead, we're using a
 //      special-cased hack for demo purposes.
-fn sql_field_ty(ty: &syn::TypePath) -> String {
-    let (ty_string, nullable) = sql_type(ty);
+fn sql_field_ty(ty: &syn::TypePath) -> syn::Result<String> {
+    let (ty_string, nullable) = sql_type(ty)?;
     let mut ty_string = ty_string.to_string();
     if !nullable {
         ty_string.push_str(" NOT NULL")
     }
-    ty_string
+    Ok(ty_string)
 }
 
 
-fn sql_type(ty: &syn::TypePath) -> (&'static str, bool) {
-    let end = ty.path.segments.last().unwrap();
-    match &*end.ident.to_string() {
+fn sql_type(ty: &syn::TypePath) -> syn::Result<(&'static str, bool)> {
+    let unsupported = || syn::Error::new_spanned(
+        ty,
+        format!(
+            "unsupported column type `{}`, expected one of `i16`, `i32`, `i64`, `f32`, \
+            `f64`, `String`, `bool`, `Oid`, or an `Option` of one of them",
+            quote!(#ty).to_string().replace(' ', ""),
+        ),
+    );
+    let end = ty.path.segments.last().ok_or_else(unsupported)?;
+    let sql_type = match &*end.ident.to_string() {
         "i16"    => ("smallint", false),
         "i32"    => ("integer", false),
         "i64"    => ("bigint", false),
@@ -204,37 +1123,149 @@ fn sql_type(ty: &syn::TypePath) -> (&'static str, bool) {
         "f64"    => ("double precision", false),
         "String" => ("text", false),
         "bool"   => ("boolean", false),
+        "Oid"    => ("oid", false),
         "Option" => {
-            let ty = option_contents(end);
+            let ty = option_contents(end)?;
+            if matches!(ty.path.segments.last(), Some(end) if end.ident == "Option") {
+                return Err(syn::Error::new_spanned(ty, "nested `Option`s are not supported"))
+            }
 
-            let (inner_type , _)= sql_type(ty);
+            let (inner_type , _)= sql_type(ty)?;
             (inner_type, true)
         },
-        _ => unimplemented!(),
+        _ => return Err(unsupported()),
+    };
+    if !matches!(end.arguments, syn::PathArguments::None) && end.ident != "Option" {
+        return Err(unsupported())
     }
+    Ok(sql_type)
 }
 
-fn option_contents(end: &syn::PathSegment) -> &syn::TypePath {
+fn option_contents(end: &syn::PathSegment) -> syn::Result<&syn::TypePath> {
+    let error = || syn::Error::new(end.span(), "expected `Option<T>` with a single type argument");
     let args = match &end.arguments {
         syn::PathArguments::AngleBracketed(args) => &args.args,
-        _ => unimplemented!(),
+        _ => return Err(error()),
     };
     if args.len() != 1 {
-        unimplemented!()
+        return Err(error())
+    }
+    match args.first() {
+        Some(syn::GenericArgument::Type(syn::Type::Path(ty))) => Ok(ty),
+        Some(arg) => Err(syn::Error::new(arg.span(), "unsupported column type")),
+        None => Err(error()),
     }
-    let arg = args.first().unwrap();
-    let ty = match arg {
-        syn::GenericArgument::Type(syn::Type::Path(ty)) => ty,
-        _ => unimplemented!()
-    };
-    ty
 }
 
 fn option_type(ty: &syn::TypePath) -> TokenStream2 {
-    let end = ty.path.segments.last().unwrap();
-    if end.ident == "Option" {
+    let is_option = matches!(ty.path.segments.last(), Some(end) if end.ident == "Option");
+    if is_option {
         return quote! { #ty };
     }
 
     quote! { Option<#ty> }
-}
\ No newline at end of file
+}
+
+// snapshots of the generated code, review changes with `cargo insta review`
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(table: Table) -> String {
+        crate::debug::pretty(&expand(table))
+    }
+
+    #[test]
+    fn column_types() {
+        let table: Table = syn::parse_quote! {
+            Types (
+                small: i16,
+                int: i32,
+                big: i64,
+                float: f32,
+                double: f64,
+                text: String,
+                flag: bool,
+                object: Oid,
+                maybe: Option<i32>,
+            )
+        };
+        insta::assert_snapshot!(sql_fields(&table.fields).unwrap());
+    }
+
+    #[test]
+    fn basic_table() {
+        let table: Table = syn::parse_quote! {
+            Example (
+                foo: i32,
+                bar: Option<String>,
+            )
+        };
+        insta::assert_snapshot!(expanded(table));
+    }
+
+    #[test]
+    fn seeded_table() {
+        let table: Table = syn::parse_quote! {
+            /// Values worth keeping.
+            pub Seeded (
+                /// The key.
+                pub id: i32,
+                name: String,
+            )
+            key: (id)
+            insert: {
+                (0..3).map(|i| (i, i.to_string()))
+            } on conflict do update
+            dump: where "id > 2"
+        };
+        insta::assert_snapshot!(expanded(table));
+    }
+
+    #[test]
+    fn versioned_table() {
+        let table: Table = syn::parse_quote! {
+            Versioned (
+                foo: i32,
+                #[changed("1.2", from = i32)]
+                bar: i64,
+                #[since = "1.1"]
+                baz: Option<String>,
+                #[since = "1.2"]
+                #[default = "0"]
+                priority: i32,
+            )
+            since: "1.0"
+            dropped: { legacy: Option<String> in "1.2" }
+        };
+        insta::assert_snapshot!(expanded(table));
+    }
+
+    #[test]
+    fn key_added_later() {
+        let table: Table = syn::parse_quote! {
+            Tenanted (
+                id: i64,
+                #[since = "1.1"]
+                #[default = "0"]
+                tenant: i32,
+            )
+            since: "1.0"
+            key: (tenant, id)
+        };
+        insta::assert_snapshot!(expanded(table));
+    }
+
+    #[test]
+    fn extern_table() {
+        let table: Table = syn::parse_quote! {
+            Other (
+                id: i64,
+                note: Option<String>,
+            )
+            name: "other_schema.other_table"
+            verify: true
+        };
+        insta::assert_snapshot!(crate::debug::pretty(&expand_extern(table)));
+    }
+}
@@ -0,0 +1,9 @@
+// Declarations and queries the macros must reject, along with the errors they
+// give. Run with `TRYBUILD=overwrite` to update the `.stderr` files after
+// changing a message.
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
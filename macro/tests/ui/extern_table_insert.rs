@@ -0,0 +1,12 @@
+use table_builder_macro::extern_table;
+
+extern_table! {
+    Example (
+        foo: i32,
+    )
+    insert: {
+        (0..3).map(|i| (i,))
+    }
+}
+
+fn main() {}
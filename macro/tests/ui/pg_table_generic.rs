@@ -0,0 +1,8 @@
+use table_builder_macro::pg_table;
+
+#[pg_table]
+struct Example<T> {
+    foo: T,
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use table_builder_macro::{extern_table, query};
+
+#[path = "support/framework.rs"]
+mod framework;
+#[path = "support/pgx.rs"]
+mod pgx;
+
+extern_table! {
+    Example (
+        foo: i32,
+        bar: String,
+    )
+}
+
+fn main() {
+    let client = pgx::SpiClient;
+    let _foos: Vec<String> = query!(client
+        from: Example
+        select: (foo)
+    ).unwrap().collect();
+}
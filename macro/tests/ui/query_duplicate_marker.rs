@@ -0,0 +1,10 @@
+use table_builder_macro::query;
+
+fn main() {
+    query!(client
+        from: Example
+        select: (foo)
+        limit: 1
+        limit: 2
+    );
+}
@@ -0,0 +1,9 @@
+use table_builder_macro::query;
+
+fn main() {
+    query!(client
+        exists: Example
+        where: "foo > 0"
+        limit: 1
+    );
+}
@@ -0,0 +1,7 @@
+use table_builder_macro::query;
+
+fn main() {
+    query!(client
+        from: Example
+    );
+}
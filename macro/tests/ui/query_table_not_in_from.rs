@@ -0,0 +1,8 @@
+use table_builder_macro::query;
+
+fn main() {
+    query!(client
+        from: Example
+        select: (Other.foo)
+    );
+}
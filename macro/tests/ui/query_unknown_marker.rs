@@ -0,0 +1,9 @@
+use table_builder_macro::query;
+
+fn main() {
+    query!(client
+        from: Example
+        select: (foo)
+        having: "count(*) > 1"
+    );
+}
@@ -0,0 +1,8 @@
+use table_builder_macro::query;
+
+fn main() {
+    query!(client
+        from: Example join Other on Example.foo = Other.foo
+        select: (foo)
+    );
+}
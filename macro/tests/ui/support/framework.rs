@@ -0,0 +1,157 @@
+// The parts of the extension's `framework` that `extern_table!` and `query!`
+// expand to, with just enough behind them for the expansion to type check.
+
+#![allow(dead_code)]
+
+pub struct Column {
+    pub name: &'static str,
+    pub sql_type: &'static str,
+    pub nullable: bool,
+}
+
+pub unsafe trait PgTable {
+    const NAME: &'static str;
+    const COLUMNS: &'static [Column];
+    const KEY: &'static [&'static str];
+    const HISTORY: &'static [upgrade::Change];
+
+    fn before_query(_client: &crate::pgx::SpiClient) {}
+
+    fn into_values(self) -> Vec<client::Value>;
+}
+
+pub unsafe trait PgTyped {
+    const SQL_TYPE: &'static str;
+    const NULLABLE: bool;
+}
+
+unsafe impl PgTyped for i32 {
+    const SQL_TYPE: &'static str = "integer";
+    const NULLABLE: bool = false;
+}
+
+unsafe impl PgTyped for String {
+    const SQL_TYPE: &'static str = "text";
+    const NULLABLE: bool = false;
+}
+
+unsafe impl<T: PgTyped> PgTyped for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+    const NULLABLE: bool = true;
+}
+
+pub trait UnwrapTo<T> {
+    fn unwrap_to(self) -> T;
+}
+
+impl<T> UnwrapTo<T> for Option<T> {
+    fn unwrap_to(self) -> T {
+        self.unwrap()
+    }
+}
+
+impl<T> UnwrapTo<Option<T>> for Option<T> {
+    fn unwrap_to(self) -> Option<T> {
+        self
+    }
+}
+
+pub mod upgrade {
+    pub struct Change;
+}
+
+pub mod schema {
+    pub fn check_extern_table<T>(_client: &crate::pgx::SpiClient) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub mod client {
+    pub type Rows<'c> = Box<dyn Iterator<Item = Vec<Value>> + 'c>;
+
+    pub trait Client {
+        fn before_query<T: super::PgTable>(&self) {}
+
+        fn select_rows<'c>(&'c self, _query: &Select) -> Result<Rows<'c>, ()> {
+            Ok(Box::new(std::iter::empty()))
+        }
+    }
+
+    impl Client for crate::pgx::SpiClient {}
+
+    pub trait Row {
+        fn get<T>(&self, ordinal: usize) -> Option<T>;
+    }
+
+    impl Row for Vec<Value> {
+        fn get<T>(&self, _ordinal: usize) -> Option<T> {
+            None
+        }
+    }
+
+    pub struct Select<'q> {
+        pub sql: &'q str,
+        pub tables: &'q [TableRef],
+        pub joins: &'q [Join],
+        pub columns: &'q [Selected],
+        pub filter: Option<&'q str>,
+        pub distinct: Distinct<'q>,
+        pub group_by: &'q [ColumnRef],
+        pub order_by: &'q [OrderBy],
+        pub limit: Option<i64>,
+        pub fetch_size: Option<i64>,
+        pub read_only: bool,
+    }
+
+    pub struct TableRef {
+        pub alias: &'static str,
+        pub name: &'static str,
+        pub columns: &'static [super::Column],
+    }
+
+    pub struct ColumnRef {
+        pub table: usize,
+        pub column: &'static str,
+    }
+
+    pub struct Join {
+        pub table: usize,
+        pub left: bool,
+        pub on: (ColumnRef, ColumnRef),
+    }
+
+    pub enum Distinct<'q> {
+        All,
+        Rows,
+        On(&'q [ColumnRef]),
+    }
+
+    pub struct OrderBy {
+        pub column: ColumnRef,
+        pub descending: bool,
+    }
+
+    pub enum Selected {
+        Column(ColumnRef),
+        Count(Option<ColumnRef>),
+        Min(ColumnRef),
+        Max(ColumnRef),
+        Sum(ColumnRef),
+    }
+
+    pub enum Value {
+        Null,
+    }
+
+    impl From<i32> for Value {
+        fn from(_: i32) -> Self {
+            Value::Null
+        }
+    }
+
+    impl From<String> for Value {
+        fn from(_: String) -> Self {
+            Value::Null
+        }
+    }
+}
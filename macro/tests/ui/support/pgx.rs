@@ -0,0 +1,10 @@
+// Stands in for the parts of pgx the expansions use.
+
+pub struct SpiClient;
+
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)*) => { panic!($($arg)*) };
+}
+#[allow(unused_imports)]
+pub(crate) use error;
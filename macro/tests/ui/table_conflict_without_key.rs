@@ -0,0 +1,12 @@
+use table_builder_macro::table;
+
+table! {
+    Example (
+        foo: i32,
+    )
+    insert: {
+        (0..3).map(|i| (i,))
+    } on conflict do nothing
+}
+
+fn main() {}
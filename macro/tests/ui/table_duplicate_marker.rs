@@ -0,0 +1,11 @@
+use table_builder_macro::table;
+
+table! {
+    Example (
+        foo: i32,
+    )
+    name: "example"
+    name: "other_example"
+}
+
+fn main() {}
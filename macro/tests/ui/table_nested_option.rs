@@ -0,0 +1,9 @@
+use table_builder_macro::table;
+
+table! {
+    Example (
+        foo: Option<Option<i32>>,
+    )
+}
+
+fn main() {}
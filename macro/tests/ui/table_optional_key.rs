@@ -0,0 +1,11 @@
+use table_builder_macro::table;
+
+table! {
+    Example (
+        foo: i32,
+        bar: Option<String>,
+    )
+    key: (foo, bar)
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use table_builder_macro::table;
+
+table! {
+    Example (
+        foo: i32,
+        #[since = "1.1"]
+        bar: i32,
+    )
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use table_builder_macro::table;
+
+table! {
+    Example (
+        foo: i32,
+        bar: Vec<String>,
+    )
+}
+
+fn main() {}